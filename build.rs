@@ -0,0 +1,26 @@
+//! Gzip-compresses the dictionaries into `OUT_DIR` so that they are embedded compressed, which
+//! keeps the binary small. They are decompressed once per run when loaded.
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+const DICTIONARIES: &[&str] = &["en", "pl"];
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    for language_code in DICTIONARIES {
+        let input_path = format!("src/dictionaries/{}.txt", language_code);
+        println!("cargo:rerun-if-changed={}", input_path);
+        let contents = fs::read(&input_path).unwrap();
+        let output_path = Path::new(&out_dir).join(format!("{}.txt.gz", language_code));
+        let mut encoder = GzEncoder::new(File::create(output_path).unwrap(), Compression::best());
+        encoder.write_all(&contents).unwrap();
+        encoder.finish().unwrap();
+    }
+}
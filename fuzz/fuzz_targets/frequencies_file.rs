@@ -0,0 +1,15 @@
+//! Reading a frequencies file, as `load_sorted_onegrams` and `load_bigrams` do, must never panic,
+//! only return errors.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use word_frequencies::frequencies::FrequenciesReader;
+
+fuzz_target!(|data: &[u8]| {
+    let reader = Box::new(std::io::Cursor::new(data.to_vec()));
+    if let Ok(reader) = FrequenciesReader::from_reader(reader, "fuzz input") {
+        for entry in reader {
+            let _ = entry;
+        }
+    }
+});
@@ -0,0 +1,11 @@
+//! Parsing one line of a cirrussearch dump must never panic, only return an error.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use word_frequencies::split::{parse_dump_line, DEFAULT_TEXT_POINTER};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = std::str::from_utf8(data) {
+        let _ = parse_dump_line(line, DEFAULT_TEXT_POINTER);
+    }
+});
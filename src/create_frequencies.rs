@@ -1,90 +1,1102 @@
+use std::borrow::Cow;
 use std::cmp::max;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::error::Error;
+use std::ffi::OsStr;
+use std::fs;
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::mpsc;
+use std::time::UNIX_EPOCH;
 
+use encoding_rs::Encoding;
 use flate2::write::GzEncoder;
 use flate2::{Compression, GzBuilder};
+use log::{debug, info, warn};
 use scoped_threadpool::Pool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use unicode_normalization::{is_nfkc_quick, IsNormalized, UnicodeNormalization};
 
-use crate::util::{get_dictionary, LineIterator, OUT_OF_VOCABULARY_WORD};
+use crate::external_sort::{write_sorted_run, SortedRunMerger};
+use crate::frequencies::{
+    BinaryFrequencies, FrequenciesEntry, FrequenciesHeader, FrequenciesReader, FrequenciesWriter,
+};
+use crate::split;
+use crate::util;
+use crate::util::{
+    get_dictionary_with_stats, trim_token, InputPiece, Script, TrimChars, OUT_OF_VOCABULARY_WORD,
+};
 
-/// Minimum number of articles that a word must be in so that it is included in the counts.
+/// A word must be in more than this many articles to be included in the counts, so a word in
+/// exactly 40 articles is left out, see `passes_article_threshold`.
 const MINIMUM_ARTICLE_THRESHOLD: u64 = 40;
 
+/// Output file name that writes the GZIP-compressed frequencies to stdout rather than to a file
+/// in the input directory.
+pub const STDOUT_OUTPUT_FILE: &str = "-";
+
+/// Sub-directory of the input directory holding sorted run files in external sort mode. It's a
+/// directory so that its contents are never mistaken for split files. Run files are named after
+/// the piece they were counted from, see `sorted_run_name`. The directory is removed once the
+/// output is written and left for inspection if counting or writing fails, until the next run
+/// removes it.
+const EXTERNAL_SORT_DIRNAME: &str = "external-sort-tmp";
+
+/// Rough in-memory size of one unigram or article count entry, assuming short words: the `String`
+/// header and contents, the count, and map overhead.
+const ESTIMATED_UNIGRAM_ENTRY_BYTES: u64 = 64;
+
+/// Rough in-memory size of one bigram entry, as for unigrams but with two `String`s.
+const ESTIMATED_BIGRAM_ENTRY_BYTES: u64 = 112;
+
+/// How many lines of an input file between memory reports with `options.report_memory`.
+const MEMORY_REPORT_INTERVAL: u64 = 100_000;
+
+/// Identifies a counts cache file, followed by `COUNTS_CACHE_VERSION`, see `save_cached_counts`.
+const COUNTS_CACHE_MAGIC: &[u8; 4] = b"WFCC";
+
+/// Version of the counts cache schema, i.e. of `NgramsResult`. Bump it whenever its fields
+/// change, older caches are then counted again rather than misread.
+const COUNTS_CACHE_VERSION: u8 = 2;
+
+/// How many distinct OOV forms `OovForms` keeps for each one reported, and at least, so that the
+/// forms reported are very likely the most frequent ones even though rarer ones are dropped.
+const OOV_FORMS_KEPT_PER_REPORTED: usize = 100;
+const MINIMUM_OOV_FORMS_KEPT: usize = 10_000;
+
+/// Optional behaviour of `create-frequencies`, set from command-line flags.
+#[derive(Debug)]
+pub struct CreateFrequenciesOptions {
+    /// Spill per-file counts to sorted run files and merge them while writing.
+    pub external_sort: bool,
+
+    /// Leave the OOV unigram and any bigram containing it out of the written output. The OOV
+    /// tokens are still included in `total unigrams`.
+    pub drop_oov: bool,
+
+    /// Leave OOV tokens out of `total unigrams`, so that unigram probabilities are conditioned on
+    /// the token being in the dictionary. OOV tokens are still counted as `<unk>`.
+    pub exclude_oov_from_total: bool,
+
+    /// Lines with more tokens than this are truncated to their first `max_tokens_per_line`
+    /// tokens, bounding memory use for malformed input.
+    pub max_tokens_per_line: usize,
+
+    /// Lowercase dictionary entries and compare tokens against the dictionary case-insensitively.
+    /// Tokens are still counted in their original case.
+    pub fold_dictionary_case: bool,
+
+    /// Also count each in-vocabulary component of a hyphenated token, e.g. "well" and "known" for
+    /// "well-known", as a unigram. Each component adds to `total unigrams`.
+    pub split_hyphens: bool,
+
+    /// NFKC-normalize lines before tokenizing, as the dictionary is, so that input not produced
+    /// by `split` still matches it. Lines that are already normalized are detected cheaply and
+    /// left as they are, so the cost is mostly one quick check per character.
+    pub nfkc_normalize: bool,
+
+    /// Soft limit on the estimated size of each thread's counts. When exceeded the counts so far
+    /// are flushed to sorted run files and counting starts afresh. Implies `external_sort`.
+    pub max_memory_per_thread: Option<u64>,
+
+    /// Which characters are trimmed from the ends of tokens, and of dictionary entries so that
+    /// they still match.
+    pub trim_chars: TrimChars,
+
+    /// Also write each unigram's article count, the number of articles it is in, as a third
+    /// column, 0 if it was only ever the last token of a line.
+    pub article_counts: bool,
+
+    /// Count in two passes: the first only counts the articles each unigram is in, so the second
+    /// can skip forming bigrams with unigrams that won't be written. Lowers peak memory at the
+    /// cost of reading the input twice. `ngram 2` then only counts the bigrams that were formed.
+    pub two_pass: bool,
+
+    /// Only measure the OOV rate over this many lines, without counting ngrams or writing output.
+    pub sample_oov: Option<u64>,
+
+    /// Skip input files that fail to be counted, reporting them, instead of aborting.
+    pub keep_going: bool,
+
+    /// Format of the output file.
+    pub output_format: OutputFormat,
+
+    /// What is counted as a token.
+    pub unit: Unit,
+
+    /// Encoding of the input files, decoded to UTF-8 before tokenizing. `None` for UTF-8, which
+    /// then must be valid.
+    pub input_encoding: Option<&'static Encoding>,
+
+    /// Periodically log the estimated size of each thread's counts and the peak RSS, to help
+    /// pick a thread count or decide whether `external_sort` is needed.
+    pub report_memory: bool,
+
+    /// Start and end tokens to put around the tokens of every article, so that the bigrams of
+    /// the first and last word are counted, as n-gram language models usually do. They are counted
+    /// like any other token but never mapped to OOV. Lines without tokens get no boundary tokens.
+    pub boundary_tokens: Option<(String, String)>,
+
+    /// Count every number, see `is_number`, as this class token instead, e.g. "<num>", so that
+    /// "in 1984" and "in 2001" are both the bigram "in <num>". The class token is never mapped
+    /// to OOV.
+    pub number_class: Option<String>,
+
+    /// Skip tokens with letters outside of this script, see `Script::is_written_in`, before
+    /// counting, as if they weren't in the text, e.g. Cyrillic or CJK fragments in an English
+    /// corpus. `None` counts every token.
+    pub script: Option<Script>,
+
+    /// Weight every token an article contributes by 1 / sqrt(article length in tokens), so that
+    /// long articles don't dominate the counts. Counts are then summed as floating point and
+    /// rounded when written, see `rounded_count`. Article counts are not weighted. Not supported
+    /// with `external_sort`, whose sorted runs hold integer counts.
+    pub length_normalize: bool,
+
+    /// Count each token as a bigram with each of the `window` tokens before it rather than only
+    /// the one right before it, i.e. skip-gram co-occurrences. 1 counts ordinary bigrams.
+    pub window: usize,
+
+    /// Weight a bigram of tokens `distance` apart within `window` by 1 / distance, as GloVe does,
+    /// rather than by 1. Like `length_normalize` this makes bigram counts fractional, rounded when
+    /// written, and isn't supported with `external_sort`.
+    pub distance_weighting: bool,
+
+    /// Count each distinct bigram at most once per article, like article counts of unigrams, so
+    /// bigram counts are the number of articles a bigram is in. Phrasing repeated within an
+    /// article, e.g. by infoboxes and navigation templates, then no longer dominates them.
+    pub dedup_bigrams_per_article: bool,
+
+    /// Count the surface forms of every token by its lowercased form, e.g. how often "apple",
+    /// "Apple" and "APPLE" each occur, and write them to a `.case.gz` sidecar file, see
+    /// `write_case_forms`. Off by default because the extra map can be as large as the unigram
+    /// counts. Not supported with `external_sort`.
+    pub track_case: bool,
+
+    /// Keep only this many of the most frequent unigrams, other than OOV, instead of those in
+    /// enough articles, folding the rest into OOV, see `fold_vocabulary`. Not supported with
+    /// `two_pass`, which prunes by article count, or `external_sort`.
+    pub max_vocab: Option<usize>,
+
+    /// Explicitly sort the rows of both sections by their words, compared byte by byte, before
+    /// writing, rather than relying on the iteration order of the counting maps, see
+    /// `sorted_by_words`. The external sort path always writes rows in this order.
+    pub sort_stable: bool,
+
+    /// Fold accented letters to their base letter, in the input and the dictionary alike, by
+    /// stripping combining marks after NFD decomposition, see `util::strip_combining_marks`.
+    pub strip_accents: bool,
+
+    /// Also write the vocabulary growth curve, the number of distinct unigrams seen after each
+    /// article, to a `.growth.gz` sidecar file, see `write_vocabulary_growth`. Not supported with
+    /// `external_sort`.
+    pub vocabulary_growth: bool,
+
+    /// Overwrite output files that already exist rather than refusing to start.
+    pub force: bool,
+
+    /// Count the largest input files first rather than in order of name. Files are handed to
+    /// threads in order, so a large file that comes last can leave every other thread idle while
+    /// it's counted; largest first is the longest-processing-time heuristic for that.
+    pub largest_first: bool,
+
+    /// Number of threads that count input files, `thread_count()` by default.
+    pub threads: u32,
+
+    /// Merge the counts of the input files in input order rather than in the order threads
+    /// finish them. Counts are sums, so this only changes results whose merge depends on order:
+    /// rounded sums of fractional weights, `vocabulary_growth` and the OOV forms kept for
+    /// `report_top_oov`. With one thread counting is then fully reproducible, e.g. to compare the
+    /// output of two versions.
+    pub deterministic: bool,
+
+    /// Save the merged counts to this file after counting, and on later runs over the same input
+    /// files with the same counting options load them from it instead of counting again, so that
+    /// only the write step is repeated, e.g. while tuning `max_vocab` or the output format, see
+    /// `counts_cache_key`. Not supported with `external_sort`, which never holds the merged counts
+    /// in memory.
+    pub cache_counts: Option<PathBuf>,
+
+    /// Count the forms of the tokens mapped to OOV and report this many of the most frequent at
+    /// the end, i.e. the words most worth adding to the dictionary, see `OovForms`. Not supported
+    /// with `external_sort`, whose sorted runs don't hold them.
+    pub report_top_oov: Option<usize>,
+
+    /// Also write a human-readable dump of the output with aligned columns, see
+    /// `write_pretty_dump`.
+    pub pretty: bool,
+
+    /// Read every input line as a JSON record, e.g. of a raw cirrussearch dump, and count the
+    /// fields these JSON pointers point to, joined by spaces, instead of the line, see
+    /// `split::record_text`. Lines without any of them, such as index lines, aren't articles. The
+    /// input files are then the JSON files in the input directory, see `split_input_files`.
+    pub input_json: Option<Vec<String>>,
+}
+
+impl CreateFrequenciesOptions {
+    /// The options `create-frequencies` uses for `language_code` when no flags are given.
+    pub fn for_language(language_code: &str) -> CreateFrequenciesOptions {
+        CreateFrequenciesOptions {
+            external_sort: false,
+            drop_oov: false,
+            exclude_oov_from_total: false,
+            max_tokens_per_line: 1_000_000,
+            fold_dictionary_case: false,
+            split_hyphens: false,
+            nfkc_normalize: true,
+            max_memory_per_thread: None,
+            trim_chars: TrimChars::for_languages(language_code),
+            article_counts: false,
+            two_pass: false,
+            sample_oov: None,
+            keep_going: false,
+            output_format: OutputFormat::Text,
+            unit: Unit::Word,
+            input_encoding: None,
+            report_memory: false,
+            boundary_tokens: None,
+            number_class: None,
+            script: None,
+            length_normalize: false,
+            window: 1,
+            distance_weighting: false,
+            dedup_bigrams_per_article: false,
+            track_case: false,
+            max_vocab: None,
+            sort_stable: false,
+            strip_accents: false,
+            vocabulary_growth: false,
+            force: false,
+            largest_first: false,
+            threads: thread_count(),
+            deterministic: false,
+            cache_counts: None,
+            report_top_oov: None,
+            pretty: false,
+            input_json: None,
+        }
+    }
+}
+
+/// What `create-frequencies` counts as a token.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    /// Words, trimmed and looked up in the dictionary.
+    Word,
+
+    /// The raw bytes of each line, without decoding, normalization or dictionary lookup, see
+    /// `calculate_byte_ngrams`.
+    Byte,
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "word" => Ok(Unit::Word),
+            "byte" => Ok(Unit::Byte),
+            _ => Err(format!("Unknown unit {}", input)),
+        }
+    }
+}
+
+/// Format of the frequencies file written by `create-frequencies`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// The gzipped text format described in `frequencies`.
+    Text,
+
+    /// A `frequencies::BinaryFrequencies` serialized with bincode, much faster to load. It's
+    /// written next to where the text output would be, with `.bin` appended instead of `.gz`.
+    Bincode,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "text" => Ok(OutputFormat::Text),
+            "bincode" => Ok(OutputFormat::Bincode),
+            _ => Err(format!("Unknown output format {}", input)),
+        }
+    }
+}
+
+impl CreateFrequenciesOptions {
+    fn is_dropped(&self, token: &str) -> bool {
+        self.drop_oov && token == OUT_OF_VOCABULARY_WORD
+    }
+
+    fn counts_towards_total(&self, token: &str) -> bool {
+        !(self.exclude_oov_from_total && token == OUT_OF_VOCABULARY_WORD)
+    }
+
+    fn is_in_vocabulary(&self, token: &str, dict: &HashSet<String>) -> bool {
+        if self.number_class.as_deref() == Some(token) {
+            true
+        } else if self.fold_dictionary_case {
+            dict.contains(&token.to_lowercase())
+        } else {
+            dict.contains(token)
+        }
+    }
+}
+
 /// References
 /// -   https://rust-lang-nursery.github.io/rust-cookbook/concurrency/threads.html
 pub fn handle_create_frequencies(
     input_dir: &Path,
-    output_file: &String,
-    language_code: &String,
+    output_file: &str,
+    language_code: &str,
+    options: &CreateFrequenciesOptions,
+) -> Result<(), Box<dyn Error>> {
+    debug!("handle_create_frequencies entry");
+    if output_file == STDOUT_OUTPUT_FILE
+        && (options.output_format == OutputFormat::Bincode
+            || options.pretty
+            || options.track_case
+            || options.vocabulary_growth)
+    {
+        let err: Box<dyn Error> = String::from(
+            "Can't write the frequencies to stdout with --format bincode, --pretty, --track-case \
+             or --vocabulary-growth, which write files next to the output file",
+        )
+        .into();
+        return Err(err);
+    }
+    if options.sample_oov.is_none() {
+        check_output_files(input_dir, output_file, options)?;
+    }
+
+    let (dictionary, dictionary_stats) = get_dictionary_with_stats(
+        language_code,
+        options.fold_dictionary_case,
+        &options.trim_chars,
+        options.strip_accents,
+    )?;
+    if options.fold_dictionary_case {
+        info!(
+            "case folding merged {} dictionary entries",
+            dictionary_stats.case_folded_entries
+        );
+    }
+    let mut input_files = split_input_files(input_dir, options.input_json.is_some())?;
+    if options.largest_first {
+        sort_largest_first(&mut input_files)?;
+    }
+    if let Some(lines) = options.sample_oov {
+        return sample_oov_rate(&input_files, &dictionary, options, lines);
+    }
+    let cache_key = match &options.cache_counts {
+        Some(cache_path) => {
+            let cache_key = counts_cache_key(&input_files, language_code, options)?;
+            if let Some(ngrams) = load_cached_counts(cache_path, &cache_key)? {
+                persist_ngrams(ngrams, input_dir, output_file, options)?;
+                return finish_output(input_dir, output_file, options);
+            }
+            Some(cache_key)
+        }
+        None => None,
+    };
+    warn_about_idle_threads(input_files.len(), options.threads);
+    let pruned_tokens = if options.two_pass {
+        info!(
+            "first pass: counting articles per unigram over {} files...",
+            input_files.len()
+        );
+        let first_pass =
+            calculate_ngrams_threaded(&input_files, &dictionary, options, Bigrams::None)?;
+        let pruned_tokens = first_pass.pruned_tokens(options);
+        info!(
+            "second pass: forming bigrams without {} unigrams that won't be written",
+            pruned_tokens.len()
+        );
+        Some(pruned_tokens)
+    } else {
+        None
+    };
+    let bigrams = match &pruned_tokens {
+        Some(pruned_tokens) => Bigrams::Excluding(pruned_tokens),
+        None => Bigrams::All,
+    };
+    info!("calculating ngrams over {} files...", input_files.len());
+    if options.external_sort || options.max_memory_per_thread.is_some() {
+        let runs_dir = input_dir.join(EXTERNAL_SORT_DIRNAME);
+        if runs_dir.is_dir() {
+            fs::remove_dir_all(&runs_dir)?;
+        }
+        fs::create_dir(&runs_dir)?;
+        let result =
+            calculate_ngrams_external(&input_files, &dictionary, &runs_dir, options, bigrams)
+                .and_then(|(runs, flushes)| {
+                    if flushes > 0 {
+                        info!("flushed partial counts to disk {} times", flushes);
+                    }
+                    report_truncated_lines(
+                        runs.iter().map(|run| run.truncated_lines).sum(),
+                        options,
+                    );
+                    persist_sorted_runs_to_file(&runs, &runs_dir, input_dir, output_file, options)
+                });
+        if let Err(err) = result {
+            warn!(
+                "leaving sorted runs in {:?} for inspection, the next run removes them",
+                runs_dir
+            );
+            return Err(err);
+        }
+        fs::remove_dir_all(&runs_dir)?;
+        if options.report_memory {
+            info!("{}", format_peak_rss());
+        }
+    } else {
+        let ngrams = calculate_ngrams_threaded(&input_files, &dictionary, options, bigrams)?;
+        if let (Some(cache_path), Some(cache_key)) = (&options.cache_counts, &cache_key) {
+            save_cached_counts(cache_path, cache_key, &ngrams)?;
+        }
+        persist_ngrams(ngrams, input_dir, output_file, options)?;
+    }
+    finish_output(input_dir, output_file, options)
+}
+
+/// Derive what is made from the finished text output: the pretty dump with `options.pretty`, and
+/// the bincode file that replaces it with `OutputFormat::Bincode`.
+fn finish_output(
+    output_dir: &Path,
+    output_file: &str,
+    options: &CreateFrequenciesOptions,
+) -> Result<(), Box<dyn Error>> {
+    if options.pretty {
+        write_pretty_dump(output_dir, output_file)?;
+    }
+    if options.output_format == OutputFormat::Bincode {
+        convert_to_bincode(output_dir, output_file)?;
+    }
+    Ok(())
+}
+
+/// Check, before spending hours counting, that none of the files that will be written exists,
+/// unless `options.force` is set. The text output is written even with `OutputFormat::Bincode`,
+/// before it's converted.
+fn check_output_files(
+    output_dir: &Path,
+    output_file: &str,
+    options: &CreateFrequenciesOptions,
+) -> Result<(), Box<dyn Error>> {
+    let mut output_paths = Vec::new();
+    if output_file != STDOUT_OUTPUT_FILE {
+        output_paths.push(output_path(output_dir, output_file, OutputFormat::Text));
+    }
+    if options.output_format == OutputFormat::Bincode {
+        output_paths.push(output_path(output_dir, output_file, OutputFormat::Bincode));
+    }
+    if options.track_case {
+        output_paths.push(output_dir.join(format!("{}.case.gz", output_file)));
+    }
+    if options.vocabulary_growth {
+        output_paths.push(output_dir.join(format!("{}.growth.gz", output_file)));
+    }
+    if options.pretty {
+        output_paths.push(pretty_dump_path(output_dir, output_file));
+    }
+    for output_path in output_paths {
+        util::check_not_overwriting(&output_path, options.force)?;
+    }
+    Ok(())
+}
+
+/// Path of the frequencies file that `handle_create_frequencies` writes for `output_file` in
+/// `output_dir`, in `format`. `STDOUT_OUTPUT_FILE` itself for stdout.
+pub fn output_path(output_dir: &Path, output_file: &str, format: OutputFormat) -> PathBuf {
+    match format {
+        OutputFormat::Text => NgramsResult::get_gzip_output_filename(output_dir, output_file),
+        OutputFormat::Bincode => output_dir.join(format!("{}.bin", output_file)),
+    }
+}
+
+/// Write merged in-memory counts, folding the vocabulary first with `options.max_vocab`.
+fn persist_ngrams(
+    mut ngrams: NgramsResult,
+    output_dir: &Path,
+    output_file: &str,
+    options: &CreateFrequenciesOptions,
+) -> Result<(), Box<dyn Error>> {
+    report_truncated_lines(ngrams.truncated_lines, options);
+    if let Some(top_oov) = options.report_top_oov {
+        // Stdout may be taken by the frequencies themselves.
+        if output_file == STDOUT_OUTPUT_FILE {
+            ngrams.oov_forms.report(top_oov, &mut io::stderr())?;
+        } else {
+            ngrams.oov_forms.report(top_oov, &mut io::stdout())?;
+        }
+    }
+    if let Some(max_vocab) = options.max_vocab {
+        ngrams = ngrams.fold_vocabulary(max_vocab, options);
+    }
+    ngrams.persist_to_file(output_dir, output_file, options)
+}
+
+/// What the counts in a counts cache depend on: the dictionary, every input piece with its size
+/// and modification time, so that changed input is counted again, and the options that change
+/// how it's counted. Options that only change what is written, such as `max_vocab`,
+/// `article_counts` or the output format, are left out so that changing them reuses the cache.
+/// `drop_oov` only changes the counts with `two_pass`, which prunes by it.
+fn counts_cache_key(
+    input_files: &[InputPiece],
+    language_code: &str,
+    options: &CreateFrequenciesOptions,
+) -> Result<String, Box<dyn Error>> {
+    let mut pieces = Vec::with_capacity(input_files.len());
+    for input_file in input_files {
+        let modified = fs::metadata(input_file.path())?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or(0);
+        pieces.push(json!({
+            "name": input_file.name(),
+            "size": input_file.compressed_size()?,
+            "modified": modified.to_string(),
+        }));
+    }
+    Ok(json!({
+        "language": language_code,
+        "pieces": pieces,
+        "max_tokens_per_line": options.max_tokens_per_line,
+        "fold_dictionary_case": options.fold_dictionary_case,
+        "split_hyphens": options.split_hyphens,
+        "nfkc_normalize": options.nfkc_normalize,
+        "trim_chars": format!("{:?}", options.trim_chars),
+        "two_pass": options.two_pass,
+        "two_pass_drop_oov": options.two_pass && options.drop_oov,
+        "exclude_oov_from_total": options.exclude_oov_from_total,
+        "keep_going": options.keep_going,
+        "unit": format!("{:?}", options.unit),
+        "input_encoding": options.input_encoding.map(Encoding::name),
+        "boundary_tokens": options.boundary_tokens,
+        "number_class": options.number_class,
+        "script": options.script.map(|script| format!("{:?}", script)),
+        "length_normalize": options.length_normalize,
+        "window": options.window,
+        "distance_weighting": options.distance_weighting,
+        "dedup_bigrams_per_article": options.dedup_bigrams_per_article,
+        "track_case": options.track_case,
+        "strip_accents": options.strip_accents,
+        "vocabulary_growth": options.vocabulary_growth,
+        "report_top_oov": options.report_top_oov,
+        "input_json": options.input_json,
+    })
+    .to_string())
+}
+
+/// The counts saved in `cache_path` if they were saved under `cache_key`. A missing, outdated or
+/// unreadable cache is logged and ignored, since the counts can always be made again.
+fn load_cached_counts(
+    cache_path: &Path,
+    cache_key: &str,
+) -> Result<Option<NgramsResult>, Box<dyn Error>> {
+    if !cache_path.is_file() {
+        info!("no counts cache at {:?}, counting", cache_path);
+        return Ok(None);
+    }
+    let read_cache = || -> Result<(String, NgramsResult), Box<dyn Error>> {
+        let mut input = BufReader::new(File::open(cache_path)?);
+        let mut magic = [0; 5];
+        input.read_exact(&mut magic)?;
+        if magic[..4] != COUNTS_CACHE_MAGIC[..] || magic[4] != COUNTS_CACHE_VERSION {
+            let err: Box<dyn Error> = String::from("not a counts cache of this version").into();
+            return Err(err);
+        }
+        Ok(bincode::deserialize_from(input)?)
+    };
+    match read_cache() {
+        Ok((key, ngrams)) if key == cache_key => {
+            info!("loaded counts from cache {:?}", cache_path);
+            Ok(Some(ngrams))
+        }
+        Ok(_) => {
+            info!(
+                "counts cache {:?} is for other input or options, counting",
+                cache_path
+            );
+            Ok(None)
+        }
+        Err(err) => {
+            warn!(
+                "ignoring unreadable counts cache {:?}: {}, counting",
+                cache_path, err
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// Save `ngrams` to `cache_path` under `cache_key`, replacing any previous cache. Written to a
+/// temporary file first so that an interruption never leaves a truncated cache.
+fn save_cached_counts(
+    cache_path: &Path,
+    cache_key: &str,
+    ngrams: &NgramsResult,
 ) -> Result<(), Box<dyn Error>> {
-    println!("handle_create_frequencies entry");
+    info!("saving counts to cache {:?}...", cache_path);
+    let mut tmp_path = cache_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let file = File::create(&tmp_path).map_err(|err| {
+        format!(
+            "Could not create counts cache {:?} due to {:?}",
+            tmp_path, err
+        )
+    })?;
+    let mut output = BufWriter::new(file);
+    output.write_all(COUNTS_CACHE_MAGIC)?;
+    output.write_all(&[COUNTS_CACHE_VERSION])?;
+    bincode::serialize_into(&mut output, &(cache_key, ngrams))?;
+    output.flush()?;
+    drop(output);
+    fs::rename(&tmp_path, cache_path)?;
+    Ok(())
+}
+
+fn pretty_dump_path(output_dir: &Path, output_file: &str) -> PathBuf {
+    output_dir.join(format!("{}.pretty.txt", output_file))
+}
 
-    let dictionary = get_dictionary(language_code)?;
-    println!("calculating ngrams...");
-    let ngrams = calculate_ngrams_threaded(input_dir, &dictionary);
-    ngrams.persist_to_file(input_dir, output_file)?;
+/// Write the text output again as `OUTPUT_FILE.pretty.txt`, uncompressed, for reading by eye:
+/// the header as comments, a heading per section and columns padded to line up, counts with
+/// thousands separators. Padding with spaces means it can't be read back, it's only for
+/// inspection. The output is read twice, first to measure the columns.
+fn write_pretty_dump(output_dir: &Path, output_file: &str) -> Result<(), Box<dyn Error>> {
+    let text_filepath = output_path(output_dir, output_file, OutputFormat::Text);
+    let pretty_filepath = pretty_dump_path(output_dir, output_file);
+    info!("writing pretty dump to {:?}...", pretty_filepath);
 
+    let mut count_width = 0;
+    let mut word_width = 0;
+    let mut unigram_rows: u64 = 0;
+    let mut bigram_rows: u64 = 0;
+    let reader = FrequenciesReader::open(&text_filepath)?;
+    let header = reader.header().clone();
+    for entry in reader {
+        match entry? {
+            FrequenciesEntry::Unigram(token, count, _article_count) => {
+                unigram_rows += 1;
+                count_width = max(count_width, thousands(count).len());
+                word_width = max(word_width, token.chars().count());
+            }
+            FrequenciesEntry::Bigram(token1, _token2, count) => {
+                bigram_rows += 1;
+                count_width = max(count_width, thousands(count).len());
+                word_width = max(word_width, token1.chars().count());
+            }
+        }
+    }
+
+    let file = File::create(&pretty_filepath).map_err(|err| {
+        format!(
+            "Could not create output file {:?} due to {:?}",
+            pretty_filepath, err
+        )
+    })?;
+    let mut output = BufWriter::new(file);
+    writeln!(
+        output,
+        "# {} for reading by eye, not meant to be parsed.",
+        output_file
+    )?;
+    writeln!(
+        output,
+        "# total unigrams:   {}",
+        thousands(header.total_unigrams)
+    )?;
+    if let Some(total_articles) = header.total_articles {
+        writeln!(output, "# total articles:   {}", thousands(total_articles))?;
+    }
+    writeln!(output, "# unigrams counted: {}", thousands(header.unigrams))?;
+    writeln!(output, "# bigrams counted:  {}", thousands(header.bigrams))?;
+    writeln!(output)?;
+    writeln!(
+        output,
+        "## Unigrams, {} rows: count, word[, articles]",
+        thousands(unigram_rows)
+    )?;
+    writeln!(output)?;
+    let mut in_bigrams = false;
+    for entry in FrequenciesReader::open(&text_filepath)? {
+        match entry? {
+            FrequenciesEntry::Unigram(token, count, Some(article_count)) => writeln!(
+                output,
+                "{:>count_width$}  {:<word_width$}  {}",
+                thousands(count),
+                token,
+                thousands(article_count)
+            )?,
+            FrequenciesEntry::Unigram(token, count, None) => {
+                writeln!(output, "{:>count_width$}  {}", thousands(count), token)?
+            }
+            FrequenciesEntry::Bigram(token1, token2, count) => {
+                if !in_bigrams {
+                    in_bigrams = true;
+                    writeln!(output)?;
+                    writeln!(
+                        output,
+                        "## Bigrams, {} rows: count, first word, second word",
+                        thousands(bigram_rows)
+                    )?;
+                    writeln!(output)?;
+                }
+                writeln!(
+                    output,
+                    "{:>count_width$}  {:<word_width$}  {}",
+                    thousands(count),
+                    token1,
+                    token2
+                )?;
+            }
+        }
+    }
+    output.flush()?;
     Ok(())
 }
 
+/// `number` with commas between groups of three digits, e.g. `1,234,567`.
+fn thousands(number: u64) -> String {
+    let digits = number.to_string();
+    let mut result = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(digit);
+    }
+    result
+}
+
+/// Replace the text output with its bincode equivalent. Converting the finished text file keeps
+/// both output paths, the in-memory and the external sort one, writing a single format.
+fn convert_to_bincode(output_dir: &Path, output_file: &str) -> Result<(), Box<dyn Error>> {
+    let text_filepath = output_path(output_dir, output_file, OutputFormat::Text);
+    let binary_filepath = output_path(output_dir, output_file, OutputFormat::Bincode);
+    info!("converting output to bincode at {:?}", binary_filepath);
+    BinaryFrequencies::from_text(&text_filepath)?.write(&binary_filepath)?;
+    fs::remove_file(&text_filepath)?;
+    Ok(())
+}
+
+fn report_truncated_lines(truncated_lines: u64, options: &CreateFrequenciesOptions) {
+    if truncated_lines > 0 {
+        warn!(
+            "truncated {} lines with more than {} tokens",
+            truncated_lines, options.max_tokens_per_line
+        );
+    }
+}
+
+fn report_skipped_rows(skipped_rows: u64) {
+    if skipped_rows > 0 {
+        warn!(
+            "skipped {} rows with a token containing a tab or line break",
+            skipped_rows
+        );
+    }
+}
+
+/// A summed count as written to the output. Counts are whole numbers unless
+/// `options.length_normalize` or `options.distance_weighting` is set, in which case they are
+/// rounded, but to at least 1 so that a token which was seen keeps a non-zero count.
+fn rounded_count(count: f64) -> u64 {
+    count.round().max(1.0) as u64
+}
+
+/// A GZIP-compressed output, a file or stdout.
+type GzipOutput = BufWriter<GzEncoder<Box<dyn Write>>>;
+
+type UnigramRows<'a> = Box<dyn Iterator<Item = (&'a String, &'a f64)> + 'a>;
+type BigramRows<'a> = Box<dyn Iterator<Item = (&'a (String, String), &'a f64)> + 'a>;
+
+/// Whether a unigram is in enough articles to be written, strictly more than
+/// `MINIMUM_ARTICLE_THRESHOLD`. Unigrams without an article count are always written. `merge`
+/// applies the same check, so keep the comparison here rather than repeating it.
+pub fn passes_article_threshold(article_count: Option<u64>) -> bool {
+    article_count.is_none_or(|article_count| article_count > MINIMUM_ARTICLE_THRESHOLD)
+}
+
 impl NgramsResult {
     fn persist_to_file(
         &self,
         output_dir: &Path,
-        output_file: &String,
+        output_file: &str,
+        options: &CreateFrequenciesOptions,
     ) -> Result<(), Box<dyn Error>> {
+        if options.track_case {
+            self.write_case_forms(output_dir, output_file)?;
+        }
+        if options.vocabulary_growth {
+            self.write_vocabulary_growth(output_dir, output_file)?;
+        }
         let gzip_output_filepath = NgramsResult::get_gzip_output_filename(output_dir, output_file);
-        println!(
+        info!(
             "NgramsResult writing frequencies to {:?}...",
             gzip_output_filepath
         );
-        let mut output_file =
-            NgramsResult::get_gzip_output_file(output_file, &gzip_output_filepath);
-        writeln!(&mut output_file, "\\data\\")?;
-        writeln!(&mut output_file, "total unigrams = {}", self.total_unigrams)?;
-        writeln!(&mut output_file, "ngram 1 = {}", self.unigram_counts.len())?;
-        writeln!(&mut output_file, "ngram 2 = {}", self.bigram_counts.len())?;
-        writeln!(&mut output_file)?;
-        writeln!(&mut output_file, "\\1-grams:")?;
-        for (token, count) in self.unigram_counts.iter() {
-            if *self
-                .unigram_article_counts
-                .get(token)
-                .unwrap_or(&u64::max_value())
-                > MINIMUM_ARTICLE_THRESHOLD
-            {
-                writeln!(&mut output_file, "{}\t{}", count, token)?;
+        let output_file = NgramsResult::get_gzip_output_file(&gzip_output_filepath)?;
+        let header = FrequenciesHeader {
+            total_unigrams: self.total_unigrams.round() as u64,
+            total_articles: Some(self.total_articles),
+            unigrams: self.unigram_counts.len() as u64,
+            bigrams: self.bigram_counts.len() as u64,
+        };
+        let mut output_file = FrequenciesWriter::new(output_file, &header)?;
+        // Sorting needs the rows collected, so that's only done when asked for.
+        let (unigram_rows, bigram_rows): (UnigramRows, BigramRows) = if options.sort_stable {
+            (
+                Box::new(self.sorted_unigram_rows().into_iter()),
+                Box::new(self.sorted_bigram_rows().into_iter()),
+            )
+        } else {
+            (
+                Box::new(self.unigram_counts.iter()),
+                Box::new(self.bigram_counts.iter()),
+            )
+        };
+
+        output_file.begin_unigrams()?;
+        for (token, count) in unigram_rows {
+            if self.should_write(token, options) {
+                if options.article_counts {
+                    let article_count = self.unigram_article_counts.get(token).copied();
+                    output_file.write_unigram_with_article_count(
+                        token,
+                        rounded_count(*count),
+                        article_count.unwrap_or(0),
+                    )?;
+                } else {
+                    output_file.write_unigram(token, rounded_count(*count))?;
+                }
             }
         }
-        writeln!(&mut output_file)?;
-        writeln!(&mut output_file, "\\2-grams:")?;
-        for ((token1, token2), count) in self.bigram_counts.iter() {
-            if *self
-                .unigram_article_counts
-                .get(token1)
-                .unwrap_or(&u64::max_value())
-                > MINIMUM_ARTICLE_THRESHOLD
-                && *self
-                    .unigram_article_counts
-                    .get(token2)
-                    .unwrap_or(&u64::max_value())
-                    > MINIMUM_ARTICLE_THRESHOLD
-            {
-                writeln!(&mut output_file, "{}\t{}\t{}", count, token1, token2)?;
+        output_file.begin_bigrams()?;
+        for ((token1, token2), count) in bigram_rows {
+            if self.should_write(token1, options) && self.should_write(token2, options) {
+                output_file.write_bigram(token1, token2, rounded_count(*count))?;
             }
         }
-        writeln!(&mut output_file)?;
-        writeln!(&mut output_file, "\\end\\")?;
+        report_skipped_rows(output_file.skipped_rows());
+        output_file.finish()?;
+
+        Ok(())
+    }
 
+    /// Write `case_form_counts` to `OUTPUT_FILE.case.gz` as `word\tform\tcount` rows, where `word`
+    /// is lowercased, sorted by word and then form. Every form is written, whether or not the word
+    /// passed the article threshold or was in the dictionary, so that a truecaser can be built
+    /// from it.
+    fn write_case_forms(&self, output_dir: &Path, output_file: &str) -> Result<(), Box<dyn Error>> {
+        let case_filepath = output_dir.join(format!("{}.case.gz", output_file));
+        info!("writing case forms to {:?}...", case_filepath);
+        let mut case_file = NgramsResult::get_gzip_output_file(&case_filepath)?;
+        for ((word, form), count) in self.case_form_counts.iter() {
+            writeln!(case_file, "{}\t{}\t{}", word, form, count)?;
+        }
+        case_file
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .finish()?;
         Ok(())
     }
 
-    fn get_gzip_output_filename(output_dir: &Path, output_file: &String) -> PathBuf {
+    /// Write the vocabulary growth curve to `OUTPUT_FILE.growth.gz`, one row per article of its
+    /// number and the number of distinct unigrams seen up to and including it, for fitting Heaps'
+    /// law. Articles are taken file by file in order of file name, so the curve is the same on
+    /// every run. The vocabulary is the unigrams that have article counts, before the article
+    /// threshold is applied.
+    fn write_vocabulary_growth(
+        &self,
+        output_dir: &Path,
+        output_file: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let growth_filepath = output_dir.join(format!("{}.growth.gz", output_file));
+        info!("writing vocabulary growth to {:?}...", growth_filepath);
+        let mut input_files: Vec<&(String, Vec<Vec<String>>)> =
+            self.new_unigrams_per_article.iter().collect();
+        input_files.sort_by(|(name1, _), (name2, _)| name1.cmp(name2));
+        let mut growth_file = NgramsResult::get_gzip_output_file(&growth_filepath)?;
+        let mut vocabulary = HashSet::new();
+        let mut article_number = 0;
+        for (_name, articles) in input_files {
+            for new_unigrams in articles {
+                article_number += 1;
+                vocabulary.extend(new_unigrams.iter().map(String::as_str));
+                writeln!(growth_file, "{}\t{}", article_number, vocabulary.len())?;
+            }
+        }
+        growth_file
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .finish()?;
+        Ok(())
+    }
+
+    /// The unigram rows sorted by their token, comparing the UTF-8 bytes, so the order doesn't
+    /// depend on the type of the counting maps.
+    fn sorted_unigram_rows(&self) -> Vec<(&String, &f64)> {
+        let mut unigram_rows: Vec<(&String, &f64)> = self.unigram_counts.iter().collect();
+        unigram_rows.sort_by(|(token1, _), (token2, _)| token1.as_bytes().cmp(token2.as_bytes()));
+        unigram_rows
+    }
+
+    /// The bigram rows sorted by their first and then second token, like `sorted_unigram_rows`.
+    fn sorted_bigram_rows(&self) -> Vec<(&(String, String), &f64)> {
+        let mut bigram_rows: Vec<(&(String, String), &f64)> = self.bigram_counts.iter().collect();
+        bigram_rows.sort_by(|((token1, token2), _), ((other1, other2), _)| {
+            token1
+                .as_bytes()
+                .cmp(other1.as_bytes())
+                .then_with(|| token2.as_bytes().cmp(other2.as_bytes()))
+        });
+        bigram_rows
+    }
+
+    fn should_write(&self, token: &str, options: &CreateFrequenciesOptions) -> bool {
+        (options.max_vocab.is_some()
+            || passes_article_threshold(self.unigram_article_counts.get(token).copied()))
+            && !options.is_dropped(token)
+    }
+
+    /// Keep the `max_vocab` most frequent unigrams other than OOV, ties broken alphabetically, and
+    /// fold every other unigram into OOV: its counts are added to OOV's and bigrams containing it
+    /// are remapped to contain OOV instead, summing bigrams that then coincide. OOV's article
+    /// count becomes the largest of the folded ones, a lower bound on the number of articles
+    /// OOV is now in. Logs the share of the unigram mass that was folded.
+    fn fold_vocabulary(self, max_vocab: usize, options: &CreateFrequenciesOptions) -> NgramsResult {
+        let mut ranked: Vec<(&String, f64)> = self
+            .unigram_counts
+            .iter()
+            .filter(|(token, _count)| *token != OUT_OF_VOCABULARY_WORD)
+            .map(|(token, count)| (token, *count))
+            .collect();
+        ranked.sort_by(|(token1, count1), (token2, count2)| {
+            count2.total_cmp(count1).then_with(|| token1.cmp(token2))
+        });
+        let kept: HashSet<String> = ranked
+            .into_iter()
+            .take(max_vocab)
+            .map(|(token, _count)| token.clone())
+            .collect();
+        let fold = |token: String| {
+            if kept.contains(&token) {
+                token
+            } else {
+                OUT_OF_VOCABULARY_WORD.to_string()
+            }
+        };
+
+        let mut folded_words = 0;
+        let mut folded_mass = 0.0;
+        let mut unigram_counts = BTreeMap::new();
+        for (token, count) in self.unigram_counts {
+            if token != OUT_OF_VOCABULARY_WORD && !kept.contains(&token) {
+                folded_words += 1;
+                folded_mass += count;
+            }
+            *unigram_counts.entry(fold(token)).or_insert(0.0) += count;
+        }
+        let mut unigram_article_counts = HashMap::new();
+        for (token, article_count) in self.unigram_article_counts {
+            let existing_count = unigram_article_counts.entry(fold(token)).or_insert(0);
+            *existing_count = max(*existing_count, article_count);
+        }
+        let mut bigram_counts = BTreeMap::new();
+        for ((token1, token2), count) in self.bigram_counts {
+            *bigram_counts
+                .entry((fold(token1), fold(token2)))
+                .or_insert(0.0) += count;
+        }
+
+        let mut total_unigrams = self.total_unigrams;
+        info!(
+            "folded {} words outside the top {} into {}, {:.2}% of the unigram mass",
+            folded_words,
+            max_vocab,
+            OUT_OF_VOCABULARY_WORD,
+            100.0 * folded_mass / total_unigrams
+        );
+        if !options.counts_towards_total(OUT_OF_VOCABULARY_WORD) {
+            total_unigrams -= folded_mass;
+        }
+        NgramsResult {
+            total_unigrams,
+            total_articles: self.total_articles,
+            unigram_counts,
+            unigram_article_counts,
+            bigram_counts,
+            truncated_lines: self.truncated_lines,
+            case_form_counts: self.case_form_counts,
+            new_unigrams_per_article: self.new_unigrams_per_article,
+            oov_forms: self.oov_forms,
+        }
+    }
+
+    /// The unigrams that won't be written, so nor will any bigram containing them.
+    fn pruned_tokens(&self, options: &CreateFrequenciesOptions) -> HashSet<String> {
+        self.unigram_counts
+            .keys()
+            .filter(|token| !self.should_write(token, options))
+            .cloned()
+            .collect()
+    }
+
+    /// Write this result as two sorted run files for a later k-way merge. Unigram rows are
+    /// `token, count, article count, whether the article count is present`, bigram rows are
+    /// `token1, token2, count`. Counts must be whole numbers, i.e. without
+    /// `options.length_normalize` or `options.distance_weighting`.
+    fn write_sorted_runs(
+        &self,
+        runs_dir: &Path,
+        run_name: &str,
+    ) -> Result<SortedRuns, Box<dyn Error>> {
+        let unigrams_path = runs_dir.join(format!("{}.unigrams", run_name));
+        write_sorted_run(
+            &unigrams_path,
+            self.unigram_counts.iter().map(|(token, count)| {
+                let article_count = self.unigram_article_counts.get(token);
+                (
+                    vec![token.as_str()],
+                    vec![
+                        *count as u64,
+                        article_count.copied().unwrap_or(0),
+                        article_count.is_some() as u64,
+                    ],
+                )
+            }),
+        )?;
+        let bigrams_path = runs_dir.join(format!("{}.bigrams", run_name));
+        write_sorted_run(
+            &bigrams_path,
+            self.bigram_counts.iter().map(|((token1, token2), count)| {
+                (vec![token1.as_str(), token2.as_str()], vec![*count as u64])
+            }),
+        )?;
+        Ok(SortedRuns {
+            total_unigrams: self.total_unigrams as u64,
+            total_articles: self.total_articles,
+            truncated_lines: self.truncated_lines,
+            unigrams_path,
+            bigrams_path,
+        })
+    }
+
+    fn get_gzip_output_filename(output_dir: &Path, output_file: &str) -> PathBuf {
+        if output_file == STDOUT_OUTPUT_FILE {
+            return PathBuf::from(STDOUT_OUTPUT_FILE);
+        }
         let output_file_path = Path::new(output_file);
         let output_file_extension = output_file_path
             .extension()
@@ -97,33 +1109,45 @@ impl NgramsResult {
         output_dir.join(output_file_path)
     }
 
-    fn get_gzip_output_file(
-        original_output_file: &String,
-        gzip_output_filepath: &PathBuf,
-    ) -> BufWriter<GzEncoder<File>> {
-        let gzip_output_file = File::create(gzip_output_filepath).unwrap_or_else(|err| {
-            panic!(
+    /// A GZIP-compressed file at `gzip_output_filepath`, or stdout if that is
+    /// `STDOUT_OUTPUT_FILE`, since `get_gzip_output_filename` passes it through.
+    fn get_gzip_output_file(gzip_output_filepath: &Path) -> Result<GzipOutput, Box<dyn Error>> {
+        if gzip_output_filepath == Path::new(STDOUT_OUTPUT_FILE) {
+            let stdout: Box<dyn Write> = Box::new(io::stdout());
+            let gzip_output_file = GzBuilder::new().write(stdout, Compression::best());
+            return Ok(BufWriter::new(gzip_output_file));
+        }
+        let gzip_output_file = File::create(gzip_output_filepath).map_err(|err| {
+            format!(
                 "Could not create output file {:?} due to {:?}",
                 gzip_output_filepath, err
             )
-        });
+        })?;
+        let gzip_output_file: Box<dyn Write> = Box::new(gzip_output_file);
         let gzip_output_file = GzBuilder::new()
-            .filename(original_output_file.as_str())
+            .filename(util::gzip_member_filename(gzip_output_filepath))
             .write(gzip_output_file, Compression::best());
-        BufWriter::new(gzip_output_file)
+        Ok(BufWriter::new(gzip_output_file))
     }
 }
 
 fn merge_ngrams_results(iter: impl Iterator<Item = NgramsResult>) -> NgramsResult {
-    let mut total_unigrams = 0;
+    let mut total_unigrams = 0.0;
+    let mut total_articles = 0;
     let mut unigram_counts = BTreeMap::new();
     let mut unigram_article_counts = HashMap::new();
     let mut bigram_counts = BTreeMap::new();
+    let mut truncated_lines = 0;
+    let mut case_form_counts = BTreeMap::new();
+    let mut new_unigrams_per_article = Vec::new();
+    let mut oov_forms = OovForms::default();
     for result in iter {
         total_unigrams += result.total_unigrams;
+        total_articles += result.total_articles;
+        truncated_lines += result.truncated_lines;
 
         for (word, count) in result.unigram_counts.into_iter() {
-            let existing_count = unigram_counts.entry(word).or_insert(0);
+            let existing_count = unigram_counts.entry(word).or_insert(0.0);
             *existing_count += count;
         }
 
@@ -133,64 +1157,447 @@ fn merge_ngrams_results(iter: impl Iterator<Item = NgramsResult>) -> NgramsResul
         }
 
         for ((word1, word2), count) in result.bigram_counts.into_iter() {
-            let existing_count = bigram_counts.entry((word1, word2)).or_insert(0);
+            let existing_count = bigram_counts.entry((word1, word2)).or_insert(0.0);
             *existing_count += count;
         }
+
+        for (key, count) in result.case_form_counts.into_iter() {
+            let existing_count = case_form_counts.entry(key).or_insert(0);
+            *existing_count += count;
+        }
+
+        new_unigrams_per_article.extend(result.new_unigrams_per_article);
+        oov_forms.merge(result.oov_forms);
     }
     NgramsResult {
         total_unigrams,
+        total_articles,
         unigram_counts,
         unigram_article_counts,
         bigram_counts,
+        truncated_lines,
+        case_form_counts,
+        new_unigrams_per_article,
+        oov_forms,
+    }
+}
+
+fn calculate_ngrams_threaded(
+    input_files: &[InputPiece],
+    dict: &HashSet<String>,
+    options: &CreateFrequenciesOptions,
+    bigrams: Bigrams,
+) -> Result<NgramsResult, Box<dyn Error>> {
+    let results = map_input_files_threaded(input_files, options, |input_file| {
+        let mut partials = Vec::new();
+        let result = calculate_ngrams(input_file, dict, options, bigrams, &mut |partial| {
+            partials.push(partial)
+        })
+        .map_err(|err| {
+            format!(
+                "failed to determine twogram counts for file {}: {}",
+                input_file, err
+            )
+        })?;
+        partials.push(result);
+        Ok(merge_ngrams_results(partials.into_iter()))
+    });
+    let ngrams = merge_ngrams_results(successful_results(results, options)?.into_iter());
+    if options.report_memory {
+        info!(
+            "merged counts estimated at {}, {}",
+            format_megabytes(estimated_counts_bytes(
+                ngrams.unigram_counts.len() + ngrams.unigram_article_counts.len(),
+                ngrams.bigram_counts.len()
+            )),
+            format_peak_rss()
+        );
     }
+    Ok(ngrams)
+}
+
+/// Rough in-memory size of counting maps with this many unigram and article count entries, and
+/// this many bigram entries.
+fn estimated_counts_bytes(unigram_entries: usize, bigram_entries: usize) -> u64 {
+    unigram_entries as u64 * ESTIMATED_UNIGRAM_ENTRY_BYTES
+        + bigram_entries as u64 * ESTIMATED_BIGRAM_ENTRY_BYTES
+}
+
+fn format_megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
 }
 
-fn calculate_ngrams_threaded(input_dir: &Path, dict: &HashSet<String>) -> NgramsResult {
-    let mut pool = Pool::new(max(num_cpus::get() as u32 - 1, 1));
+fn format_peak_rss() -> String {
+    match util::peak_rss_bytes() {
+        Some(bytes) => format!("peak RSS {}", format_megabytes(bytes)),
+        None => String::from("peak RSS unknown"),
+    }
+}
+
+/// Count each input file separately and spill its counts to sorted run files, so that at most one
+/// file's counts per thread are in memory at a time. Also returns how many times counts were
+/// flushed early because of `max_memory_per_thread`.
+fn calculate_ngrams_external(
+    input_files: &[InputPiece],
+    dict: &HashSet<String>,
+    runs_dir: &Path,
+    options: &CreateFrequenciesOptions,
+    bigrams: Bigrams,
+) -> Result<(Vec<SortedRuns>, u64), Box<dyn Error>> {
+    let mut piece_names = HashSet::new();
+    for input_file in input_files {
+        if !piece_names.insert(input_file.name()) {
+            let err: Box<dyn Error> = format!(
+                "More than one input piece is named {}, so their sorted runs would overwrite each other",
+                input_file.name()
+            )
+            .into();
+            return Err(err);
+        }
+    }
+    let results = map_input_files_threaded(input_files, options, |input_file| {
+        let file_name = input_file.name();
+        let mut file_runs = Vec::new();
+        let mut write_error = None;
+        let mut write_runs = |result: NgramsResult| {
+            if write_error.is_some() {
+                return;
+            }
+            let run_name = sorted_run_name(&file_name, file_runs.len());
+            match result.write_sorted_runs(runs_dir, &run_name) {
+                Ok(runs) => file_runs.push(runs),
+                Err(err) => write_error = Some(err.to_string()),
+            }
+        };
+        let result = calculate_ngrams(input_file, dict, options, bigrams, &mut write_runs)
+            .map_err(|err| {
+                format!(
+                    "failed to determine twogram counts for file {}: {}",
+                    input_file, err
+                )
+            })?;
+        write_runs(result);
+        match write_error {
+            Some(err) => Err(format!(
+                "failed to write sorted runs for file {}: {}",
+                input_file, err
+            )),
+            None => Ok(file_runs),
+        }
+    });
+    let mut flushes = 0;
+    let mut runs = Vec::new();
+    for file_runs in successful_results(results, options)? {
+        flushes += file_runs.len() as u64 - 1;
+        runs.extend(file_runs);
+    }
+    Ok((runs, flushes))
+}
+
+/// Name of the `flush`th sorted run of the piece named `piece_name`, counting from 0, its
+/// unigrams and bigrams being written to `RUN_NAME.unigrams` and `RUN_NAME.bigrams`. Derived
+/// only from the piece, which is unique within a run, so that threads never collide and the same
+/// input always leaves the same files to inspect.
+fn sorted_run_name(piece_name: &str, flush: usize) -> String {
+    format!("{}.{}", piece_name, flush)
+}
+
+/// The results of the input files that were counted successfully. Without `options.keep_going`
+/// any failure is returned as an error, with it failures are reported and skipped.
+fn successful_results<T>(
+    results: impl Iterator<Item = Result<T, String>>,
+    options: &CreateFrequenciesOptions,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    let mut successes = Vec::new();
+    let mut failures = 0;
+    for result in results {
+        match result {
+            Ok(result) => successes.push(result),
+            Err(err) if options.keep_going => {
+                warn!("{}, skipping it", err);
+                failures += 1;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    if failures > 0 {
+        warn!(
+            "skipped {} input files that failed, the output only covers the other {}",
+            failures,
+            successes.len()
+        );
+    }
+    Ok(successes)
+}
+
+/// Sort input files by decreasing compressed size, ties by name, see `options.largest_first`.
+fn sort_largest_first(input_files: &mut Vec<InputPiece>) -> io::Result<()> {
+    let mut sized_files = input_files
+        .drain(..)
+        .map(|input_file| Ok((input_file.compressed_size()?, input_file)))
+        .collect::<io::Result<Vec<_>>>()?;
+    sized_files.sort_by(|(size1, file1), (size2, file2)| {
+        size2
+            .cmp(size1)
+            .then_with(|| file1.name().cmp(&file2.name()))
+    });
+    input_files.extend(
+        sized_files
+            .into_iter()
+            .map(|(_size, input_file)| input_file),
+    );
+    debug!(
+        "counting largest first: {}",
+        input_files
+            .iter()
+            .map(|input_file| input_file.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(())
+}
+
+/// The pieces to count in `input_dir`: every file with "split" in its stem, except that the
+/// archive of a `split --single-file` is counted per piece as listed in its index. With
+/// `json_input`, every file with ".json" in its name instead, e.g. a `*.json.gz` dump, each a
+/// single piece. Errors if there are none, since counting would otherwise silently produce an
+/// empty model.
+fn split_input_files(
+    input_dir: &Path,
+    json_input: bool,
+) -> Result<Vec<InputPiece>, Box<dyn Error>> {
+    if json_input {
+        return json_input_files(input_dir);
+    }
+    let mut split_files = Vec::new();
+    for entry in input_dir.read_dir()? {
+        let path = entry?.path();
+        let is_split_file = path.is_file()
+            && path
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .is_some_and(|file_stem| file_stem.contains("split"));
+        if is_split_file {
+            split_files.push(path);
+        }
+    }
+    split_files.sort();
+    let mut archives = HashSet::new();
+    let mut archive_pieces = Vec::new();
+    for index_path in split_files
+        .iter()
+        .filter(|path| split::is_single_file_index(path))
+    {
+        let (archive_path, pieces) = split::load_single_file_index(index_path)?;
+        archives.insert(archive_path);
+        archive_pieces.extend(pieces);
+    }
+    let input_files: Vec<InputPiece> = split_files
+        .into_iter()
+        .filter(|path| !split::is_single_file_index(path) && !archives.contains(path))
+        .map(InputPiece::whole_file)
+        .chain(archive_pieces)
+        .collect();
+    if input_files.is_empty() {
+        let err: Box<dyn Error> = format!(
+            "No split files found in {:?} matching pattern '*split*', run the 'split' sub-command first",
+            input_dir
+        )
+        .into();
+        return Err(err);
+    }
+    Ok(input_files)
+}
+
+fn json_input_files(input_dir: &Path) -> Result<Vec<InputPiece>, Box<dyn Error>> {
+    let mut json_files = Vec::new();
+    for entry in input_dir.read_dir()? {
+        let path = entry?.path();
+        let is_json_file = path.is_file()
+            && path
+                .file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|file_name| file_name.contains(".json"));
+        if is_json_file {
+            json_files.push(path);
+        }
+    }
+    if json_files.is_empty() {
+        let err: Box<dyn Error> = format!(
+            "No JSON files found in {:?} matching pattern '*.json*' for --input-json",
+            input_dir
+        )
+        .into();
+        return Err(err);
+    }
+    json_files.sort();
+    Ok(json_files.into_iter().map(InputPiece::whole_file).collect())
+}
+
+/// Run `f` over every input file on a pool of `options.threads` threads, returning the results
+/// in completion order, or in input order with `options.deterministic`. With one thread or one
+/// file the files are counted in order on the calling thread instead, without a pool, which
+/// gives the same results in input order and keeps backtraces simple.
+fn map_input_files_threaded<T, F>(
+    input_files: &[InputPiece],
+    options: &CreateFrequenciesOptions,
+    f: F,
+) -> impl Iterator<Item = T>
+where
+    T: Send,
+    F: Fn(&InputPiece) -> T + Sync,
+{
+    if options.threads == 1 || input_files.len() <= 1 {
+        let results: Vec<T> = input_files.iter().map(f).collect();
+        return results.into_iter();
+    }
+    let mut pool = Pool::new(options.threads);
     let (tx, rx) = mpsc::channel();
+    let f = &f;
     pool.scoped(|scope| {
-        input_dir
-            .read_dir()
-            .unwrap()
-            .map(|entry| entry.unwrap())
-            .map(|entry| entry.path())
-            .filter(|path| path.is_file())
-            .filter(|path| {
-                path.file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .contains("split")
-            })
-            .for_each(|input_file| {
-                let tx = tx.clone();
-                scope.execute(move || {
-                    let result = calculate_ngrams(input_file.as_ref(), dict);
-                    if result.is_ok() {
-                        tx.send(result.unwrap()).unwrap();
-                    } else {
-                        panic!(
-                            "failed to determine twogram counts for file {:?}: {:?}",
-                            input_file, result
-                        );
-                    }
-                });
-            });
+        for (i, input_file) in input_files.iter().enumerate() {
+            let tx = tx.clone();
+            scope.execute(move || tx.send((i, f(input_file))).unwrap());
+        }
     });
     drop(tx);
-    merge_ngrams_results(rx.iter())
+    let mut results: Vec<(usize, T)> = rx.into_iter().collect();
+    if options.deterministic {
+        results.sort_by_key(|(i, _result)| *i);
+    }
+    results
+        .into_iter()
+        .map(|(_i, result)| result)
+        .collect::<Vec<T>>()
+        .into_iter()
 }
 
-#[derive(Debug)]
+/// Input files are counted in parallel one file per thread, so with fewer files than threads some
+/// threads have nothing to do. Splitting a file further isn't possible without decompressing it,
+/// so all that can be done here is to suggest more pieces.
+fn warn_about_idle_threads(input_files: usize, threads: u32) {
+    let threads = threads as usize;
+    if input_files < threads {
+        warn!(
+            "only {} input files for {} counting threads, so {} threads will be idle; split with \
+             --pieces {} or more to use them all",
+            input_files,
+            threads,
+            threads - input_files,
+            threads
+        );
+    }
+}
+
+/// Default number of threads used to count input files, leaving one core free.
+pub fn thread_count() -> u32 {
+    max(num_cpus::get() as u32 - 1, 1)
+}
+
+/// Sorted run files written by one worker in external sort mode.
+struct SortedRuns {
+    total_unigrams: u64,
+    total_articles: u64,
+    truncated_lines: u64,
+    unigrams_path: PathBuf,
+    bigrams_path: PathBuf,
+}
+
+/// Merge sorted run files and stream the result to the output file. Each section is merged into
+/// a temporary file first because the header needs the number of distinct ngrams. Only the set of
+/// unigrams that pass the article threshold is held in memory, for filtering bigrams.
+fn persist_sorted_runs_to_file(
+    runs: &[SortedRuns],
+    runs_dir: &Path,
+    output_dir: &Path,
+    output_file: &str,
+    options: &CreateFrequenciesOptions,
+) -> Result<(), Box<dyn Error>> {
+    info!("merging {} sorted runs...", runs.len());
+    let total_unigrams = runs.iter().map(|run| run.total_unigrams).sum();
+    let total_articles = runs.iter().map(|run| run.total_articles).sum();
+
+    let merged_unigrams_path = runs_dir.join("merged.unigrams");
+    let mut merged_unigrams =
+        FrequenciesWriter::rows_only(BufWriter::new(File::create(&merged_unigrams_path)?));
+    let mut surviving_unigrams = HashSet::new();
+    let mut unigrams = 0;
+    let unigram_paths: Vec<&Path> = runs.iter().map(|run| run.unigrams_path.as_ref()).collect();
+    for row in SortedRunMerger::new(&unigram_paths, 1)? {
+        let (mut key, values) = row?;
+        unigrams += 1;
+        let article_count = if values[2] > 0 { Some(values[1]) } else { None };
+        let token = key.pop().unwrap();
+        if passes_article_threshold(article_count) && !options.is_dropped(&token) {
+            if options.article_counts {
+                merged_unigrams.write_unigram_with_article_count(
+                    &token,
+                    values[0],
+                    article_count.unwrap_or(0),
+                )?;
+            } else {
+                merged_unigrams.write_unigram(&token, values[0])?;
+            }
+            surviving_unigrams.insert(token);
+        }
+    }
+    let mut skipped_rows = merged_unigrams.skipped_rows();
+    merged_unigrams.into_inner().flush()?;
+
+    let merged_bigrams_path = runs_dir.join("merged.bigrams");
+    let mut merged_bigrams =
+        FrequenciesWriter::rows_only(BufWriter::new(File::create(&merged_bigrams_path)?));
+    let mut bigrams = 0;
+    let bigram_paths: Vec<&Path> = runs.iter().map(|run| run.bigrams_path.as_ref()).collect();
+    for row in SortedRunMerger::new(&bigram_paths, 2)? {
+        let (key, values) = row?;
+        bigrams += 1;
+        if surviving_unigrams.contains(&key[0]) && surviving_unigrams.contains(&key[1]) {
+            merged_bigrams.write_bigram(&key[0], &key[1], values[0])?;
+        }
+    }
+    skipped_rows += merged_bigrams.skipped_rows();
+    report_skipped_rows(skipped_rows);
+    merged_bigrams.into_inner().flush()?;
+
+    let gzip_output_filepath = NgramsResult::get_gzip_output_filename(output_dir, output_file);
+    info!(
+        "SortedRuns writing frequencies to {:?}...",
+        gzip_output_filepath
+    );
+    let output_file = NgramsResult::get_gzip_output_file(&gzip_output_filepath)?;
+    let header = FrequenciesHeader {
+        total_unigrams,
+        total_articles: Some(total_articles),
+        unigrams,
+        bigrams,
+    };
+    let mut output_file = FrequenciesWriter::new(output_file, &header)?;
+    output_file.begin_unigrams()?;
+    output_file.copy_rows(&mut File::open(&merged_unigrams_path)?)?;
+    output_file.begin_bigrams()?;
+    output_file.copy_rows(&mut File::open(&merged_bigrams_path)?)?;
+    output_file.finish()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 struct NgramsResult {
     /// Total number of unigrams in the corpus. The probability of a given unigram is the frequency
-    /// of the unigram divided by this.
-    total_unigrams: u64,
+    /// of the unigram divided by this. Counts are floating point so that they can be weighted, see
+    /// `options.length_normalize` and `options.distance_weighting`, but are whole numbers
+    /// otherwise.
+    total_unigrams: f64,
+
+    /// Number of articles, i.e. input lines, counted. Needed to turn article counts into document
+    /// frequencies.
+    total_articles: u64,
 
     /// Counts of specific unigrams. When you divide this by total_unigrams you get the
     /// unigram probability. If a unigram occurs more than once in a given article it is incremented
     /// more than once in this count.
-    unigram_counts: BTreeMap<String, u64>,
+    unigram_counts: BTreeMap<String, f64>,
 
     /// Number of articles that a given unigram is in. If a unigfram occurs more than once in a
     /// given article then this count is increment by 1 only.
@@ -198,53 +1605,409 @@ struct NgramsResult {
 
     /// Counts of specific bigrams. The probability of a bigram (w_1, w_2) is the count of
     /// (w_1, w_2) divided by the count of w_1, which you can get from unigram_counts.
-    bigram_counts: BTreeMap<(String, String), u64>,
+    bigram_counts: BTreeMap<(String, String), f64>,
+
+    /// Number of lines that were truncated for having more than the maximum tokens per line.
+    truncated_lines: u64,
+
+    /// Counts of the surface forms of tokens, keyed by the lowercased token and then the form as
+    /// it occurred, before mapping to OOV. Empty unless `options.track_case` is set.
+    case_form_counts: BTreeMap<(String, String), u64>,
+
+    /// For each input file, by name, and each of its articles in order, the unigrams that the
+    /// article is the first in that file to contain. Files are counted in parallel, so their
+    /// order here is arbitrary. Empty unless `options.vocabulary_growth` is set.
+    new_unigrams_per_article: Vec<(String, Vec<Vec<String>>)>,
+
+    /// Counts of the forms of tokens mapped to OOV. Empty unless `options.report_top_oov` is set.
+    oov_forms: OovForms,
+}
+
+/// Counts of the forms of tokens that were mapped to OOV, bounded in size: once there are twice
+/// `capacity` forms only the `capacity` most frequent are kept, so memory stays bounded however
+/// many distinct unknown words a corpus has. A form that was dropped and seen again starts
+/// counting from 0, so after pruning counts are lower bounds, but frequent forms are seen often
+/// enough to survive every pruning.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OovForms {
+    counts: HashMap<String, u64>,
+    capacity: usize,
+    pruned: bool,
 }
 
+impl OovForms {
+    /// Forms counted to report the `top_oov` most frequent.
+    fn new(top_oov: usize) -> OovForms {
+        OovForms {
+            capacity: max(
+                top_oov.saturating_mul(OOV_FORMS_KEPT_PER_REPORTED),
+                MINIMUM_OOV_FORMS_KEPT,
+            ),
+            ..OovForms::default()
+        }
+    }
+
+    fn add(&mut self, form: &str) {
+        match self.counts.get_mut(form) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(form.to_string(), 1);
+                if self.counts.len() > 2 * self.capacity {
+                    self.prune();
+                }
+            }
+        }
+    }
+
+    fn merge(&mut self, other: OovForms) {
+        self.capacity = max(self.capacity, other.capacity);
+        self.pruned |= other.pruned;
+        for (form, count) in other.counts {
+            *self.counts.entry(form).or_insert(0) += count;
+        }
+        if self.counts.len() > self.capacity {
+            self.prune();
+        }
+    }
+
+    fn prune(&mut self) {
+        let counts = mem::take(&mut self.counts);
+        let capacity = self.capacity;
+        self.counts = OovForms::sorted(counts)
+            .into_iter()
+            .take(capacity)
+            .collect();
+        self.pruned = true;
+    }
+
+    /// Forms by decreasing count, ties alphabetically.
+    fn sorted(counts: HashMap<String, u64>) -> Vec<(String, u64)> {
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        counts.sort_by(|(form1, count1), (form2, count2)| {
+            count2.cmp(count1).then_with(|| form1.cmp(form2))
+        });
+        counts
+    }
+
+    /// Print the `top_oov` most frequent forms as `count\tform` lines to `output`.
+    fn report(&self, top_oov: usize, output: &mut dyn Write) -> io::Result<()> {
+        if self.pruned {
+            warn!(
+                "more than {} distinct OOV words, so their counts are lower bounds",
+                self.capacity
+            );
+        }
+        writeln!(output, "most frequent OOV words:")?;
+        for (form, count) in OovForms::sorted(self.counts.clone())
+            .into_iter()
+            .take(top_oov)
+        {
+            writeln!(output, "{}\t{}", count, form)?;
+        }
+        Ok(())
+    }
+}
+
+/// The text to count of an input line: the line itself, or the text of its record with
+/// `options.input_json`, `None` if the record isn't an article. Parsing JSON here, once per line
+/// of the counting loop, is a good deal slower than reading lines that `split` already extracted.
+fn line_text<'a>(
+    line: &'a str,
+    options: &CreateFrequenciesOptions,
+) -> io::Result<Option<Cow<'a, str>>> {
+    match &options.input_json {
+        Some(text_pointers) => split::record_text(line, text_pointers)
+            .map(|text| text.map(Cow::Owned))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string())),
+        None => Ok(Some(Cow::Borrowed(line))),
+    }
+}
+
+/// NFKC-normalize a line if `options.nfkc_normalize` is set and it isn't already normalized, then
+/// strip its accents if `options.strip_accents` is set.
+fn normalize_line<'a>(line: &'a str, options: &CreateFrequenciesOptions) -> Cow<'a, str> {
+    let line = if options.nfkc_normalize && is_nfkc_quick(line.chars()) != IsNormalized::Yes {
+        Cow::Owned(line.nfkc().collect::<String>())
+    } else {
+        Cow::Borrowed(line)
+    };
+    if options.strip_accents && !line.is_ascii() {
+        Cow::Owned(util::strip_combining_marks(&line))
+    } else {
+        line
+    }
+}
+
+/// Split a line into tokens, trimming punctuation from each, skipping those outside of
+/// `options.script` and replacing numbers with `options.number_class` if they're set.
+fn tokenize<'a>(
+    text: &'a str,
+    options: &'a CreateFrequenciesOptions,
+) -> impl Iterator<Item = &'a str> + 'a {
+    text.split_whitespace()
+        .map(move |token| trim_token(token, &options.trim_chars))
+        // Tokens made up entirely of punctuation, e.g. "---", are empty after trimming and
+        // are not words at all, so drop them rather than counting them as OOV.
+        .filter(|token| !token.is_empty())
+        .filter(move |token| match options.script {
+            Some(script) => script.is_written_in(token),
+            None => true,
+        })
+        .map(move |token| match &options.number_class {
+            Some(number_class) if is_number(token) => number_class.as_str(),
+            _ => token,
+        })
+}
+
+/// Whether a token is a number: digits, in any script, optionally with the decimal and
+/// thousands separators '.' and ',', e.g. "1984", "3.14" or "1,000". Tokens that merely contain
+/// digits, e.g. "1990s" or "B2", are words.
+fn is_number(token: &str) -> bool {
+    token.contains(char::is_numeric)
+        && token
+            .chars()
+            .all(|c| c.is_numeric() || c == '.' || c == ',')
+}
+
+/// Tokenize the first `lines` lines of the input files, in order, and report how many of the
+/// tokens are out of the dictionary. A quick check of the language and dictionary before a long
+/// counting run.
+fn sample_oov_rate(
+    input_files: &[InputPiece],
+    dict: &HashSet<String>,
+    options: &CreateFrequenciesOptions,
+    lines: u64,
+) -> Result<(), Box<dyn Error>> {
+    let mut lines_sampled = 0;
+    let mut in_vocabulary_tokens: u64 = 0;
+    let mut oov_tokens: u64 = 0;
+    let mut oov_forms = options
+        .report_top_oov
+        .map_or_else(OovForms::default, OovForms::new);
+    'files: for input_file in input_files {
+        for line in input_file.lines()?.with_encoding(options.input_encoding) {
+            if lines_sampled == lines {
+                break 'files;
+            }
+            let line = line?;
+            let line_borrowed = line.borrow();
+            let record = match line_text(&line_borrowed, options)? {
+                Some(record) => record,
+                None => continue,
+            };
+            lines_sampled += 1;
+            let text = normalize_line(&record, options);
+            for token in tokenize(&text, options).take(options.max_tokens_per_line) {
+                if options.is_in_vocabulary(token, dict) {
+                    in_vocabulary_tokens += 1;
+                } else {
+                    oov_tokens += 1;
+                    if options.report_top_oov.is_some() {
+                        oov_forms.add(token);
+                    }
+                }
+            }
+        }
+    }
+    let total_tokens = in_vocabulary_tokens + oov_tokens;
+    println!("lines sampled = {}", lines_sampled);
+    println!("in-vocabulary tokens = {}", in_vocabulary_tokens);
+    println!("OOV tokens = {}", oov_tokens);
+    if total_tokens > 0 {
+        println!(
+            "OOV rate = {:.2}%",
+            100.0 * oov_tokens as f64 / total_tokens as f64
+        );
+    }
+    if let Some(top_oov) = options.report_top_oov {
+        oov_forms.report(top_oov, &mut io::stdout())?;
+    }
+    Ok(())
+}
+
+/// Which adjacent token pairs `calculate_ngrams` counts as bigrams.
+#[derive(Debug, Clone, Copy)]
+enum Bigrams<'a> {
+    All,
+
+    /// No bigrams, for the first pass of two-pass counting which only needs article counts.
+    None,
+
+    /// Only pairs where neither token is in this set.
+    Excluding(&'a HashSet<String>),
+}
+
+/// Count the ngrams in one input file. If `options.max_memory_per_thread` is set and the
+/// estimated size of the counts exceeds it, the counts so far are passed to `flush_partial` and
+/// counting continues from empty; the returned result then only covers the rest of the file.
 fn calculate_ngrams(
-    input_file: &Path,
+    input_file: &InputPiece,
     dict: &HashSet<String>,
-) -> Result<NgramsResult, std::io::Error> {
-    let mut total_unigrams = 0;
+    options: &CreateFrequenciesOptions,
+    bigrams: Bigrams,
+    flush_partial: &mut dyn FnMut(NgramsResult),
+) -> io::Result<NgramsResult> {
+    if options.unit == Unit::Byte {
+        return calculate_byte_ngrams(input_file, options, bigrams);
+    }
+    let mut total_unigrams = 0.0;
+    let mut total_articles = 0;
     let mut unigram_counts = BTreeMap::new();
     let mut unigram_article_counts = HashMap::new();
     let mut bigram_counts = BTreeMap::new();
-    for line in LineIterator::new(input_file).unwrap() {
+    let mut truncated_lines = 0;
+    let mut case_form_counts = BTreeMap::new();
+    let mut new_unigrams = Vec::new();
+    let mut oov_forms = options
+        .report_top_oov
+        .map_or_else(OovForms::default, OovForms::new);
+    let mut lines_read: u64 = 0;
+    let lines = input_file
+        .lines()
+        .map_err(|err| io::Error::other(err.to_string()))?
+        .with_encoding(options.input_encoding);
+    for line in lines {
+        let line = line?;
         let line_borrowed = line.borrow();
-        let tokens: Vec<&str> = line_borrowed
-            .split_whitespace()
-            .map(|token| {
-                token.trim_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace())
-            })
-            .map(|token| {
-                if dict.contains(token) {
-                    token
-                } else {
-                    OUT_OF_VOCABULARY_WORD
-                }
-            })
-            .collect();
+        let record = match line_text(&line_borrowed, options)? {
+            Some(record) => record,
+            None => continue,
+        };
+        total_articles += 1;
+        let text = normalize_line(&record, options);
+        // Weighting needs the article length up front, so only then tokenize the line twice.
+        let weight = if options.length_normalize {
+            let article_length = tokenize(&text, options)
+                .take(options.max_tokens_per_line)
+                .count();
+            1.0 / (article_length as f64).sqrt()
+        } else {
+            1.0
+        };
+        // Boundary tokens are flagged so that they are neither truncated nor mapped to OOV. Taking
+        // one token more than the maximum is enough to tell whether the line was truncated.
+        let (start_token, end_token) = match &options.boundary_tokens {
+            Some((start, end)) => (Some((start.as_str(), true)), Some((end.as_str(), true))),
+            None => (None, None),
+        };
+        let tokens = start_token
+            .into_iter()
+            .chain(
+                tokenize(&text, options)
+                    .take(options.max_tokens_per_line + 1)
+                    .map(|token| (token, false)),
+            )
+            .chain(end_token);
+
+        // Stream over the tokens with a lookback of `window` tokens rather than collecting them.
+        // Each token is counted as a unigram once its successor is seen, and as a bigram with
+        // each token in the lookback, most recent first.
         let mut seen_unigrams = HashSet::new();
-        for (token1, token2) in tokens.iter().zip(tokens.iter().skip(1)) {
-            total_unigrams += 1;
+        let mut seen_bigrams = HashSet::new();
+        let mut previous_tokens: VecDeque<&str> = VecDeque::with_capacity(options.window);
+        let mut line_tokens = 0;
+        let mut counted_tokens = 0;
+        for (token, is_boundary) in tokens {
+            if is_boundary && !previous_tokens.is_empty() && line_tokens == 0 {
+                // Don't count a line without tokens as a bigram of the boundary tokens.
+                break;
+            }
+            if !is_boundary {
+                if line_tokens == options.max_tokens_per_line {
+                    truncated_lines += 1;
+                    continue;
+                }
+                line_tokens += 1;
+            }
+            counted_tokens += 1;
 
-            let unigram_entry = unigram_counts.entry((*token1).to_string()).or_insert(0);
-            *unigram_entry += 1;
+            if options.track_case && !is_boundary {
+                let case_form_entry = case_form_counts
+                    .entry((token.to_lowercase(), token.to_string()))
+                    .or_insert(0);
+                *case_form_entry += 1;
+            }
 
-            seen_unigrams.insert(*token1);
+            if options.split_hyphens && !is_boundary && token.contains('-') {
+                // Components are counted as extra unigrams only, bigrams still use the whole token.
+                for component in token.split('-').filter(|component| {
+                    !component.is_empty() && options.is_in_vocabulary(component, dict)
+                }) {
+                    total_unigrams += weight;
+                    let unigram_entry = unigram_counts.entry(component.to_string()).or_insert(0.0);
+                    *unigram_entry += weight;
+                    seen_unigrams.insert(component);
+                }
+            }
 
-            let bigram_entry = bigram_counts
-                .entry(((*token1).to_string(), (*token2).to_string()))
-                .or_insert(0);
-            *bigram_entry += 1;
+            let token = if is_boundary || options.is_in_vocabulary(token, dict) {
+                token
+            } else {
+                if options.report_top_oov.is_some() {
+                    oov_forms.add(token);
+                }
+                OUT_OF_VOCABULARY_WORD
+            };
+            if let Some(&token1) = previous_tokens.front() {
+                if options.counts_towards_total(token1) {
+                    total_unigrams += weight;
+                }
+
+                let unigram_entry = unigram_counts.entry(token1.to_string()).or_insert(0.0);
+                *unigram_entry += weight;
+
+                seen_unigrams.insert(token1);
+            }
+            for (i, &token1) in previous_tokens.iter().enumerate() {
+                let form_bigram = match bigrams {
+                    Bigrams::All => true,
+                    Bigrams::None => false,
+                    Bigrams::Excluding(pruned_tokens) => {
+                        !pruned_tokens.contains(token1) && !pruned_tokens.contains(token)
+                    }
+                };
+                // With deduplication only an article's first occurrence of a bigram is counted.
+                if form_bigram
+                    && (!options.dedup_bigrams_per_article || seen_bigrams.insert((token1, token)))
+                {
+                    let distance = i + 1;
+                    let bigram_weight = if options.distance_weighting {
+                        weight / distance as f64
+                    } else {
+                        weight
+                    };
+                    let bigram_entry = bigram_counts
+                        .entry((token1.to_string(), token.to_string()))
+                        .or_insert(0.0);
+                    *bigram_entry += bigram_weight;
+                }
+            }
+            if previous_tokens.len() == options.window {
+                previous_tokens.pop_back();
+            }
+            previous_tokens.push_front(token);
         }
 
-        // The iteration above missed the last token as a unigram so we tack it on here.
-        if tokens.len() >= 2 {
-            let last_token = tokens[tokens.len() - 1];
-            total_unigrams += 1;
-            let unigram_entry = unigram_counts.entry(last_token.to_string()).or_insert(0);
-            *unigram_entry += 1;
+        // The loop above only counts a token once it has a successor, so the last token is
+        // tacked on here. Lines with a single token are not counted at all.
+        if counted_tokens >= 2 {
+            let last_token = *previous_tokens.front().unwrap();
+            if options.counts_towards_total(last_token) {
+                total_unigrams += weight;
+            }
+            let unigram_entry = unigram_counts.entry(last_token.to_string()).or_insert(0.0);
+            *unigram_entry += weight;
+        }
+
+        // A unigram is new to this file if no earlier article in it has an article count for it.
+        if options.vocabulary_growth {
+            new_unigrams.push(
+                seen_unigrams
+                    .iter()
+                    .filter(|unigram| !unigram_article_counts.contains_key(**unigram))
+                    .map(|unigram| unigram.to_string())
+                    .collect(),
+            );
         }
 
         for unigram in seen_unigrams {
@@ -253,11 +2016,447 @@ fn calculate_ngrams(
                 .or_insert(0);
             *unigram_article_entry += 1;
         }
+
+        lines_read += 1;
+        if options.report_memory && lines_read.is_multiple_of(MEMORY_REPORT_INTERVAL) {
+            info!(
+                "counts for {} after {} lines estimated at {}, {}",
+                input_file,
+                lines_read,
+                format_megabytes(estimated_counts_bytes(
+                    unigram_counts.len() + unigram_article_counts.len(),
+                    bigram_counts.len()
+                )),
+                format_peak_rss()
+            );
+        }
+
+        if let Some(max_memory) = options.max_memory_per_thread {
+            let estimated_memory = estimated_counts_bytes(
+                unigram_counts.len() + unigram_article_counts.len(),
+                bigram_counts.len(),
+            );
+            if estimated_memory > max_memory {
+                info!(
+                    "counts for {} estimated at {} bytes, flushing partial counts",
+                    input_file, estimated_memory
+                );
+                flush_partial(NgramsResult {
+                    total_unigrams,
+                    total_articles,
+                    unigram_counts: mem::take(&mut unigram_counts),
+                    unigram_article_counts: mem::take(&mut unigram_article_counts),
+                    bigram_counts: mem::take(&mut bigram_counts),
+                    truncated_lines,
+                    case_form_counts: mem::take(&mut case_form_counts),
+                    new_unigrams_per_article: Vec::new(),
+                    oov_forms: mem::take(&mut oov_forms),
+                });
+                total_unigrams = 0.0;
+                total_articles = 0;
+                truncated_lines = 0;
+            }
+        }
     }
     Ok(NgramsResult {
         total_unigrams,
+        total_articles,
         unigram_counts,
         unigram_article_counts,
         bigram_counts,
+        truncated_lines,
+        case_form_counts,
+        new_unigrams_per_article: if options.vocabulary_growth {
+            vec![(input_file.name(), new_unigrams)]
+        } else {
+            Vec::new()
+        },
+        oov_forms,
     })
 }
+
+/// Count byte unigrams and bigrams in one input file, reading each line's bytes as they are, so
+/// that input which isn't valid UTF-8 can be counted too. Bytes are written as two lowercase hex
+/// digits, e.g. `e2`, and the line break ending each line isn't counted. Unlike words every byte
+/// is counted, including single bytes and the last byte of a line, so `total_unigrams` is the
+/// number of bytes. At most 256 unigrams and 65,536 bigrams exist, so counts are never flushed.
+fn calculate_byte_ngrams(
+    input_file: &InputPiece,
+    options: &CreateFrequenciesOptions,
+    bigrams: Bigrams,
+) -> io::Result<NgramsResult> {
+    let mut total_unigrams = 0.0;
+    let mut total_articles = 0;
+    let mut truncated_lines = 0;
+    let mut unigram_counts = vec![0.0; 256];
+    let mut unigram_article_counts = vec![0; 256];
+    let mut bigram_counts = vec![0.0; 256 * 256];
+    let mut reader = input_file.open()?;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        total_articles += 1;
+        let mut bytes = &line[..];
+        if bytes.len() > options.max_tokens_per_line {
+            truncated_lines += 1;
+            bytes = &bytes[..options.max_tokens_per_line];
+        }
+        let weight = if options.length_normalize {
+            1.0 / (bytes.len() as f64).sqrt()
+        } else {
+            1.0
+        };
+
+        let mut seen = [false; 256];
+        for byte in bytes {
+            total_unigrams += weight;
+            unigram_counts[*byte as usize] += weight;
+            seen[*byte as usize] = true;
+        }
+        for (byte, seen) in seen.iter().enumerate() {
+            if *seen {
+                unigram_article_counts[byte] += 1;
+            }
+        }
+        for pair in bytes.windows(2) {
+            let form_bigram = match bigrams {
+                Bigrams::All => true,
+                Bigrams::None => false,
+                Bigrams::Excluding(pruned_tokens) => {
+                    !pruned_tokens.contains(&byte_token(pair[0]))
+                        && !pruned_tokens.contains(&byte_token(pair[1]))
+                }
+            };
+            if form_bigram {
+                bigram_counts[pair[0] as usize * 256 + pair[1] as usize] += weight;
+            }
+        }
+    }
+
+    let mut result = NgramsResult {
+        total_unigrams,
+        total_articles,
+        unigram_counts: BTreeMap::new(),
+        unigram_article_counts: HashMap::new(),
+        bigram_counts: BTreeMap::new(),
+        truncated_lines,
+        case_form_counts: BTreeMap::new(),
+        new_unigrams_per_article: Vec::new(),
+        oov_forms: OovForms::default(),
+    };
+    for byte in 0..=255u8 {
+        if unigram_article_counts[byte as usize] > 0 {
+            let token = byte_token(byte);
+            result
+                .unigram_counts
+                .insert(token.clone(), unigram_counts[byte as usize]);
+            result
+                .unigram_article_counts
+                .insert(token, unigram_article_counts[byte as usize]);
+        }
+    }
+    for (pair, count) in bigram_counts.into_iter().enumerate() {
+        if count > 0.0 {
+            result.bigram_counts.insert(
+                (
+                    byte_token((pair / 256) as u8),
+                    byte_token((pair % 256) as u8),
+                ),
+                count,
+            );
+        }
+    }
+    Ok(result)
+}
+
+/// How a byte is written as a token with `Unit::Byte`.
+fn byte_token(byte: u8) -> String {
+    format!("{:02x}", byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn article_threshold_is_strict() {
+        assert!(!passes_article_threshold(Some(40)));
+        assert!(passes_article_threshold(Some(41)));
+        assert!(passes_article_threshold(None));
+    }
+
+    #[test]
+    fn persist_to_file_writes_only_words_above_the_article_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut ngrams = NgramsResult {
+            total_unigrams: 120.0,
+            total_articles: 120,
+            ..NgramsResult::default()
+        };
+        for (word, articles) in &[("of", 41), ("and", 40), ("to", 39)] {
+            ngrams
+                .unigram_counts
+                .insert(word.to_string(), *articles as f64);
+            ngrams
+                .unigram_article_counts
+                .insert(word.to_string(), *articles);
+        }
+        // A bigram is only written if both of its words are.
+        for (token1, token2) in &[("of", "of"), ("of", "and"), ("to", "of")] {
+            ngrams
+                .bigram_counts
+                .insert((token1.to_string(), token2.to_string()), 5.0);
+        }
+        ngrams
+            .persist_to_file(
+                dir.path(),
+                "frequencies.txt",
+                &CreateFrequenciesOptions::for_language("en"),
+            )
+            .unwrap();
+
+        let reader = FrequenciesReader::open(&dir.path().join("frequencies.txt.gz")).unwrap();
+        let entries: Vec<FrequenciesEntry> = reader.map(Result::unwrap).collect();
+        assert_eq!(
+            entries,
+            vec![
+                FrequenciesEntry::Unigram(String::from("of"), 41, None),
+                FrequenciesEntry::Bigram(String::from("of"), String::from("of"), 5),
+            ]
+        );
+    }
+
+    /// Split a fixture dump into 8 pieces in `dir`, returning the directory of the pieces.
+    fn split_fixture(dir: &Path) -> PathBuf {
+        let vocabulary: Vec<String> = ["the", "of", "and", "to", "in"]
+            .iter()
+            .map(|word| word.to_string())
+            .collect();
+        crate::fixtures::handle_make_fixtures(dir, 200, 20, &vocabulary, 7).unwrap();
+        let split_dir = dir.join("split");
+        split::handle_split(
+            &dir.join("fixture-cirrussearch.json.gz"),
+            &split_dir,
+            8,
+            split::PieceAssignment::Random,
+            false,
+            util::InputCompression::Auto,
+            1,
+            None,
+            false,
+            None,
+            split::DEFAULT_TEXT_POINTER,
+            false,
+        )
+        .unwrap();
+        split_dir
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        let split_dir = split_fixture(dir.path());
+        let input_files = split_input_files(&split_dir, false).unwrap();
+        let mut options = CreateFrequenciesOptions::for_language("en");
+        let (dictionary, _stats) =
+            get_dictionary_with_stats("en", false, &options.trim_chars, false).unwrap();
+
+        options.threads = 1;
+        let one_thread =
+            calculate_ngrams_threaded(&input_files, &dictionary, &options, Bigrams::All).unwrap();
+        options.threads = 4;
+        let four_threads =
+            calculate_ngrams_threaded(&input_files, &dictionary, &options, Bigrams::All).unwrap();
+
+        assert!(!one_thread.bigram_counts.is_empty());
+        assert_eq!(one_thread.total_unigrams, four_threads.total_unigrams);
+        assert_eq!(one_thread.total_articles, four_threads.total_articles);
+        assert_eq!(one_thread.unigram_counts, four_threads.unigram_counts);
+        assert_eq!(one_thread.bigram_counts, four_threads.bigram_counts);
+        assert_eq!(
+            one_thread.unigram_article_counts,
+            four_threads.unigram_article_counts
+        );
+    }
+
+    #[test]
+    fn punctuation_only_tokens_are_dropped() {
+        let options = CreateFrequenciesOptions::for_language("en");
+        assert_eq!(
+            tokenize("hello --- world", &options).collect::<Vec<_>>(),
+            vec!["hello", "world"]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let piece_path = dir.path().join("t.split.000.gz");
+        let mut piece = GzEncoder::new(File::create(&piece_path).unwrap(), Compression::default());
+        writeln!(piece, "hello --- world").unwrap();
+        piece.finish().unwrap();
+        let (dictionary, _stats) =
+            get_dictionary_with_stats("en", false, &options.trim_chars, false).unwrap();
+        let ngrams = calculate_ngrams(
+            &InputPiece::whole_file(piece_path),
+            &dictionary,
+            &options,
+            Bigrams::All,
+            &mut |_partial| {},
+        )
+        .unwrap();
+        assert_eq!(
+            ngrams.unigram_counts.keys().collect::<Vec<_>>(),
+            vec!["hello", "world"]
+        );
+        assert_eq!(
+            ngrams.bigram_counts.keys().collect::<Vec<_>>(),
+            vec![&(String::from("hello"), String::from("world"))]
+        );
+    }
+
+    #[test]
+    fn streaming_lookback_counts_like_collecting_each_line() {
+        let text = [
+            "the cat sat on the mat",
+            "cat",
+            "",
+            "--- the the the ---",
+            "a qwzxv word and another qwzxv",
+            "one two three four five six seven eight",
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let piece_path = dir.path().join("t.split.000.gz");
+        let mut piece = GzEncoder::new(File::create(&piece_path).unwrap(), Compression::default());
+        for line in &text {
+            writeln!(piece, "{}", line).unwrap();
+        }
+        piece.finish().unwrap();
+        let mut options = CreateFrequenciesOptions::for_language("en");
+        options.max_tokens_per_line = 5;
+        let (dictionary, _stats) =
+            get_dictionary_with_stats("en", false, &options.trim_chars, false).unwrap();
+        let streamed = calculate_ngrams(
+            &InputPiece::whole_file(piece_path),
+            &dictionary,
+            &options,
+            Bigrams::All,
+            &mut |_partial| {},
+        )
+        .unwrap();
+
+        // Collect each line's tokens and count adjacent pairs, as counting did before streaming.
+        let mut total_unigrams = 0.0;
+        let mut unigram_counts = BTreeMap::new();
+        let mut unigram_article_counts = HashMap::new();
+        let mut bigram_counts = BTreeMap::new();
+        let mut truncated_lines = 0;
+        for line in &text {
+            let mut tokens: Vec<&str> = tokenize(line, &options)
+                .map(|token| {
+                    if options.is_in_vocabulary(token, &dictionary) {
+                        token
+                    } else {
+                        OUT_OF_VOCABULARY_WORD
+                    }
+                })
+                .collect();
+            if tokens.len() > options.max_tokens_per_line {
+                tokens.truncate(options.max_tokens_per_line);
+                truncated_lines += 1;
+            }
+            let mut seen_unigrams = HashSet::new();
+            for (token1, token2) in tokens.iter().zip(tokens.iter().skip(1)) {
+                total_unigrams += 1.0;
+                *unigram_counts.entry(token1.to_string()).or_insert(0.0) += 1.0;
+                seen_unigrams.insert(*token1);
+                *bigram_counts
+                    .entry((token1.to_string(), token2.to_string()))
+                    .or_insert(0.0) += 1.0;
+            }
+            if tokens.len() >= 2 {
+                total_unigrams += 1.0;
+                *unigram_counts
+                    .entry(tokens[tokens.len() - 1].to_string())
+                    .or_insert(0.0) += 1.0;
+            }
+            for unigram in seen_unigrams {
+                *unigram_article_counts
+                    .entry(unigram.to_string())
+                    .or_insert(0) += 1;
+            }
+        }
+
+        assert!(unigram_counts.contains_key(OUT_OF_VOCABULARY_WORD));
+        assert_eq!(truncated_lines, 3);
+        assert_eq!(streamed.total_unigrams, total_unigrams);
+        assert_eq!(streamed.unigram_counts, unigram_counts);
+        assert_eq!(streamed.bigram_counts, bigram_counts);
+        assert_eq!(streamed.unigram_article_counts, unigram_article_counts);
+        assert_eq!(streamed.truncated_lines, truncated_lines);
+    }
+
+    #[test]
+    fn empty_input_directory_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_file = dir.path().join("frequencies.txt");
+        let err = handle_create_frequencies(
+            dir.path(),
+            output_file.to_str().unwrap(),
+            "en",
+            &CreateFrequenciesOptions::for_language("en"),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("No split files found"),
+            "{}",
+            err
+        );
+        assert!(!dir.path().join("frequencies.txt.gz").exists());
+    }
+
+    #[test]
+    fn sort_stable_output_is_byte_identical_across_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let split_dir = split_fixture(dir.path());
+        let mut options = CreateFrequenciesOptions::for_language("en");
+        options.sort_stable = true;
+        options.threads = 4;
+        let mut outputs = Vec::new();
+        for run in &["1", "2"] {
+            let run_dir = dir.path().join(run);
+            fs::create_dir(&run_dir).unwrap();
+            let output_file = run_dir.join("frequencies.txt");
+            handle_create_frequencies(&split_dir, output_file.to_str().unwrap(), "en", &options)
+                .unwrap();
+            outputs.push(fs::read(run_dir.join("frequencies.txt.gz")).unwrap());
+        }
+        assert!(!outputs[0].is_empty());
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn output_embeds_its_file_name_in_the_gzip_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let split_dir = split_fixture(dir.path());
+        let output_file = dir.path().join("frequencies.txt");
+        handle_create_frequencies(
+            &split_dir,
+            output_file.to_str().unwrap(),
+            "en",
+            &CreateFrequenciesOptions::for_language("en"),
+        )
+        .unwrap();
+        let mut decoder = flate2::read::GzDecoder::new(
+            File::open(dir.path().join("frequencies.txt.gz")).unwrap(),
+        );
+        io::copy(&mut decoder, &mut io::sink()).unwrap();
+        assert_eq!(
+            decoder.header().and_then(|header| header.filename()),
+            Some(&b"frequencies.txt"[..])
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::Path;
+
+use crate::frequencies::FrequenciesReader;
+use crate::util::{collation_key, get_dictionary_with_stats, TrimChars, OUT_OF_VOCABULARY_WORD};
+
+pub fn handle_dict_info(language_code: &str, fold_case: bool) -> Result<(), Box<dyn Error>> {
+    let (_dictionary, stats) = get_dictionary_with_stats(
+        language_code,
+        fold_case,
+        &TrimChars::for_languages(language_code),
+        false,
+    )?;
+    println!("dictionary: {}", language_code);
+    println!("raw lines = {}", stats.raw_lines);
+    println!(
+        "lines after comment/empty filtering = {}",
+        stats.filtered_lines
+    );
+    println!(
+        "lines changed by nfkc normalization = {}",
+        stats.nfkc_changed_lines
+    );
+    println!("unique entries = {}", stats.unique_entries);
+    println!("duplicates collapsed = {}", stats.duplicates_collapsed);
+    if fold_case {
+        println!(
+            "entries merged by case folding = {}",
+            stats.case_folded_entries
+        );
+    }
+    Ok(())
+}
+
+/// Compare the unigrams of a frequencies file with the dictionary for `language_code`, printing
+/// the dictionary words that never occur in it, in the language's alphabetical order, and the
+/// `top_missing` most frequent words in it that aren't in the dictionary, with their counts. A
+/// file made by `create-frequencies` with the same dictionary has no such words, they are all
+/// counted as OOV, so the second list is only useful for files made with another dictionary or by
+/// `from-counts`; otherwise `create-frequencies --report-top-oov` lists them.
+pub fn handle_dict_diff(
+    input_file: &Path,
+    language_code: &str,
+    fold_case: bool,
+    top_missing: usize,
+) -> Result<(), Box<dyn Error>> {
+    let (dictionary, _stats) = get_dictionary_with_stats(
+        language_code,
+        fold_case,
+        &TrimChars::for_languages(language_code),
+        false,
+    )?;
+    let mut corpus_words = HashSet::new();
+    let mut missing = Vec::new();
+    let mut oov_count = None;
+    for unigram in FrequenciesReader::open(input_file)?.unigrams() {
+        let (token, count) = unigram?;
+        if token == OUT_OF_VOCABULARY_WORD {
+            oov_count = Some(count);
+            continue;
+        }
+        let word = if fold_case {
+            token.to_lowercase()
+        } else {
+            token.clone()
+        };
+        if !dictionary.contains(&word) {
+            missing.push((token, count));
+        }
+        corpus_words.insert(word);
+    }
+    let mut unseen: Vec<&String> = dictionary
+        .iter()
+        .filter(|word| !corpus_words.contains(*word))
+        .collect();
+    unseen.sort_by_cached_key(|word| collation_key(word, language_code));
+    missing.sort_by(|(word1, count1), (word2, count2)| {
+        count2.cmp(count1).then_with(|| word1.cmp(word2))
+    });
+
+    println!("dictionary: {}", language_code);
+    println!("dictionary words = {}", dictionary.len());
+    println!("dictionary words not in the corpus = {}", unseen.len());
+    println!("corpus words not in the dictionary = {}", missing.len());
+    if let Some(oov_count) = oov_count {
+        println!("{} count = {}", OUT_OF_VOCABULARY_WORD, oov_count);
+    }
+    println!();
+    println!("\\dictionary words not in the corpus:");
+    for word in unseen {
+        println!("{}", word);
+    }
+    println!();
+    println!("\\corpus words not in the dictionary:");
+    for (word, count) in missing.iter().take(top_missing) {
+        println!("{}\t{}", count, word);
+    }
+    Ok(())
+}
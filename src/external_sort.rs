@@ -0,0 +1,97 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+
+/// A row in a sorted run file: tab-separated key columns followed by tab-separated counts.
+pub type Row = (Vec<String>, Vec<u64>);
+
+/// The next unread row of a run, ordered by key and then by run index.
+type HeapEntry = Reverse<(Vec<String>, usize, Vec<u64>)>;
+
+/// Write rows, which must already be sorted by key, to a run file that `SortedRunMerger` can
+/// read back.
+pub fn write_sorted_run<'a>(
+    path: &Path,
+    rows: impl Iterator<Item = (Vec<&'a str>, Vec<u64>)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut output_file = BufWriter::with_capacity(1024 * 1024, File::create(path)?);
+    for (key, values) in rows {
+        output_file.write_all(key.join("\t").as_bytes())?;
+        for value in values {
+            write!(&mut output_file, "\t{}", value)?;
+        }
+        output_file.write_all(b"\n")?;
+    }
+    output_file.flush()?;
+    Ok(())
+}
+
+/// K-way merge over sorted run files. Rows with equal keys across runs are combined into one,
+/// summing their counts, so only one row per run is held in memory at a time.
+pub struct SortedRunMerger {
+    runs: Vec<Lines<BufReader<File>>>,
+    heap: BinaryHeap<HeapEntry>,
+    key_columns: usize,
+}
+
+impl SortedRunMerger {
+    pub fn new(paths: &[&Path], key_columns: usize) -> io::Result<SortedRunMerger> {
+        let mut merger = SortedRunMerger {
+            runs: Vec::with_capacity(paths.len()),
+            heap: BinaryHeap::with_capacity(paths.len()),
+            key_columns,
+        };
+        for path in paths {
+            let run = BufReader::with_capacity(1024 * 1024, File::open(path)?).lines();
+            merger.runs.push(run);
+            merger.advance(merger.runs.len() - 1)?;
+        }
+        Ok(merger)
+    }
+
+    fn advance(&mut self, run_index: usize) -> io::Result<()> {
+        if let Some(line) = self.runs[run_index].next() {
+            let line = line?;
+            let mut columns = line.split('\t');
+            let key: Vec<String> = columns
+                .by_ref()
+                .take(self.key_columns)
+                .map(String::from)
+                .collect();
+            let values = columns
+                .map(|column| column.parse::<u64>())
+                .collect::<Result<Vec<u64>, _>>()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            self.heap.push(Reverse((key, run_index, values)));
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for SortedRunMerger {
+    type Item = io::Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((key, run_index, mut values)) = self.heap.pop()?;
+        if let Err(err) = self.advance(run_index) {
+            return Some(Err(err));
+        }
+        while let Some(Reverse((next_key, _, _))) = self.heap.peek() {
+            if *next_key != key {
+                break;
+            }
+            let Reverse((_, next_run_index, next_values)) = self.heap.pop().unwrap();
+            for (value, next_value) in values.iter_mut().zip(next_values) {
+                *value += next_value;
+            }
+            if let Err(err) = self.advance(next_run_index) {
+                return Some(Err(err));
+            }
+        }
+        Some(Ok((key, values)))
+    }
+}
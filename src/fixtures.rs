@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::{Compression, GzBuilder};
+use log::info;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde_json::json;
+
+use crate::frequencies::{FrequenciesHeader, FrequenciesWriter};
+use crate::util::gzip_member_filename;
+
+pub const CIRRUSSEARCH_FIXTURE_FILENAME: &str = "fixture-cirrussearch.json.gz";
+pub const FREQUENCIES_FIXTURE_FILENAME: &str = "fixture-frequencies.txt.gz";
+
+/// Write a small cirrussearch dump of `articles` random articles of `words_per_article` words
+/// each, and the frequencies file with their exact counts, to `output_dir`. Words are sampled
+/// from `vocabulary` with Zipf-like weights, the first word being the most frequent, so fixtures
+/// look like real text to the sub-commands that rank words.
+///
+/// The frequencies file has every unigram and bigram, with article counts, and no
+/// out-of-vocabulary token, i.e. what `create-frequencies --article-counts` would write if every
+/// word were in the dictionary and no unigram were filtered out.
+pub fn handle_make_fixtures(
+    output_dir: &Path,
+    articles: usize,
+    words_per_article: usize,
+    vocabulary: &[String],
+    seed: u64,
+) -> Result<(), Box<dyn Error>> {
+    if vocabulary.is_empty() {
+        let err: Box<dyn Error> = String::from("Vocabulary cannot be empty").into();
+        return Err(err);
+    }
+    fs::create_dir_all(output_dir)?;
+
+    let distribution = WeightedIndex::new((1..=vocabulary.len()).map(|rank| 1.0 / rank as f64))?;
+    let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+    let texts: Vec<Vec<&str>> = (0..articles)
+        .map(|_| {
+            (0..words_per_article)
+                .map(|_| vocabulary[distribution.sample(&mut rng)].as_str())
+                .collect()
+        })
+        .collect();
+
+    write_cirrussearch(&output_dir.join(CIRRUSSEARCH_FIXTURE_FILENAME), &texts)?;
+    write_frequencies(&output_dir.join(FREQUENCIES_FIXTURE_FILENAME), &texts)?;
+    info!(
+        "wrote {} articles to {} and {} in {:?}",
+        articles, CIRRUSSEARCH_FIXTURE_FILENAME, FREQUENCIES_FIXTURE_FILENAME, output_dir
+    );
+
+    Ok(())
+}
+
+/// `count` distinct lowercase ASCII words: "a" to "z", then "aa", "ab" and so on.
+pub fn generated_vocabulary(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|mut i| {
+            let mut word = Vec::new();
+            loop {
+                word.push(b'a' + (i % 26) as u8);
+                if i < 26 {
+                    break;
+                }
+                i = i / 26 - 1;
+            }
+            word.reverse();
+            String::from_utf8(word).unwrap()
+        })
+        .collect()
+}
+
+fn create_gzip_file(output_file: &Path) -> Result<BufWriter<GzEncoder<File>>, Box<dyn Error>> {
+    let file = File::create(output_file).map_err(|err| {
+        format!(
+            "Could not create output file {:?} due to {:?}",
+            output_file, err
+        )
+    })?;
+    Ok(BufWriter::new(
+        GzBuilder::new()
+            .filename(gzip_member_filename(output_file))
+            .write(file, Compression::default()),
+    ))
+}
+
+/// Each article as an index line followed by a line with its title and text, as in the dumps.
+fn write_cirrussearch(output_file: &Path, texts: &[Vec<&str>]) -> Result<(), Box<dyn Error>> {
+    let mut output = create_gzip_file(output_file)?;
+    for (i, text) in texts.iter().enumerate() {
+        writeln!(output, "{}", json!({"index": {"_id": i}}))?;
+        writeln!(
+            output,
+            "{}",
+            json!({"title": format!("Article {}", i), "text": text.join(" ")})
+        )?;
+    }
+    output
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .finish()?;
+    Ok(())
+}
+
+fn write_frequencies(output_file: &Path, texts: &[Vec<&str>]) -> Result<(), Box<dyn Error>> {
+    let mut unigram_counts: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    let mut bigram_counts: BTreeMap<(&str, &str), u64> = BTreeMap::new();
+    for text in texts {
+        let mut seen = HashSet::new();
+        for word in text {
+            let (count, article_count) = unigram_counts.entry(word).or_default();
+            *count += 1;
+            if seen.insert(word) {
+                *article_count += 1;
+            }
+        }
+        for pair in text.windows(2) {
+            *bigram_counts.entry((pair[0], pair[1])).or_default() += 1;
+        }
+    }
+
+    let header = FrequenciesHeader {
+        total_unigrams: texts.iter().map(|text| text.len() as u64).sum(),
+        total_articles: Some(texts.len() as u64),
+        unigrams: unigram_counts.len() as u64,
+        bigrams: bigram_counts.len() as u64,
+    };
+    let mut output = FrequenciesWriter::new(create_gzip_file(output_file)?, &header)?;
+    output.begin_unigrams()?;
+    for (token, (count, article_count)) in unigram_counts.iter() {
+        output.write_unigram_with_article_count(token, *count, *article_count)?;
+    }
+    output.begin_bigrams()?;
+    for ((token1, token2), count) in bigram_counts.iter() {
+        output.write_bigram(token1, token2, *count)?;
+    }
+    output
+        .finish()?
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .finish()?;
+    Ok(())
+}
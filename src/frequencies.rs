@@ -0,0 +1,553 @@
+//! The frequencies file format written by `create-frequencies` and read by every other
+//! sub-command. It is a subset of the ARPA language model format, with counts in place of log
+//! probabilities. Columns within a row are separated by tabs:
+//!
+//! ```text
+//! \data\
+//! total unigrams = 123
+//! total articles = 4
+//! ngram 1 = 2
+//! ngram 2 = 1
+//!
+//! \1-grams:
+//! 100 the
+//! 23  cat
+//!
+//! \2-grams:
+//! 20  the cat
+//!
+//! \end\
+//! ```
+//!
+//! `create-frequencies --format bincode` instead writes the same data as a `BinaryFrequencies`
+//! serialized with bincode, after a magic number and a format version byte. It is much faster to
+//! load and `FrequenciesReader` reads it transparently.
+//!
+//! Files written with `--article-counts` have the number of articles each unigram is in as a
+//! third column of the unigram rows. Files written before `total articles` was added lack it.
+
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::{LineIterator, OUT_OF_VOCABULARY_WORD};
+
+pub const DATA_MARKER: &str = "\\data\\";
+pub const UNIGRAMS_MARKER: &str = "\\1-grams:";
+pub const BIGRAMS_MARKER: &str = "\\2-grams:";
+pub const END_MARKER: &str = "\\end\\";
+
+const TOTAL_UNIGRAMS_KEY: &str = "total unigrams";
+const TOTAL_ARTICLES_KEY: &str = "total articles";
+const UNIGRAMS_KEY: &str = "ngram 1";
+const BIGRAMS_KEY: &str = "ngram 2";
+
+/// Values from the `\data\` section.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FrequenciesHeader {
+    /// Total number of unigrams in the corpus, the denominator for unigram probabilities.
+    pub total_unigrams: u64,
+
+    /// Number of articles counted, missing from files written by older versions.
+    pub total_articles: Option<u64>,
+
+    /// Number of distinct unigrams counted, before any were filtered out of the file.
+    pub unigrams: u64,
+
+    /// Number of distinct bigrams counted, before any were filtered out of the file.
+    pub bigrams: u64,
+}
+
+/// One row of the unigram or bigram section. Unigrams have an article count if the file was
+/// written with one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrequenciesEntry {
+    Unigram(String, u64, Option<u64>),
+    Bigram(String, String, u64),
+}
+
+/// Writes a frequencies file section by section: `new` writes the header, then call
+/// `begin_unigrams`, `write_unigram` for each row, `begin_bigrams`, `write_bigram` for each row
+/// and finally `finish`.
+///
+/// Rows with a token containing a tab or line break are skipped, since they would corrupt the
+/// format, and counted in `skipped_rows`. Tokenizing on whitespace means `create-frequencies`
+/// never produces such tokens, so this only guards against future tokenizer changes.
+pub struct FrequenciesWriter<W: Write> {
+    writer: W,
+    skipped_rows: u64,
+}
+
+impl<W: Write> FrequenciesWriter<W> {
+    pub fn new(mut writer: W, header: &FrequenciesHeader) -> io::Result<FrequenciesWriter<W>> {
+        writeln!(writer, "{}", DATA_MARKER)?;
+        writeln!(writer, "{} = {}", TOTAL_UNIGRAMS_KEY, header.total_unigrams)?;
+        if let Some(total_articles) = header.total_articles {
+            writeln!(writer, "{} = {}", TOTAL_ARTICLES_KEY, total_articles)?;
+        }
+        writeln!(writer, "{} = {}", UNIGRAMS_KEY, header.unigrams)?;
+        writeln!(writer, "{} = {}", BIGRAMS_KEY, header.bigrams)?;
+        writeln!(writer)?;
+        Ok(FrequenciesWriter::rows_only(writer))
+    }
+
+    /// A writer for rows only, without a header or section markers. Used to stage sections in
+    /// temporary files that are then copied into a full file with `copy_rows`.
+    pub fn rows_only(writer: W) -> FrequenciesWriter<W> {
+        FrequenciesWriter {
+            writer,
+            skipped_rows: 0,
+        }
+    }
+
+    pub fn begin_unigrams(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "{}", UNIGRAMS_MARKER)
+    }
+
+    pub fn write_unigram(&mut self, token: &str, count: u64) -> io::Result<()> {
+        if !is_writable_token(token) {
+            self.skipped_rows += 1;
+            return Ok(());
+        }
+        writeln!(self.writer, "{}\t{}", count, token)
+    }
+
+    /// A unigram row with the number of articles the unigram is in as a third column.
+    pub fn write_unigram_with_article_count(
+        &mut self,
+        token: &str,
+        count: u64,
+        article_count: u64,
+    ) -> io::Result<()> {
+        if !is_writable_token(token) {
+            self.skipped_rows += 1;
+            return Ok(());
+        }
+        writeln!(self.writer, "{}\t{}\t{}", count, token, article_count)
+    }
+
+    pub fn begin_bigrams(&mut self) -> io::Result<()> {
+        writeln!(self.writer)?;
+        writeln!(self.writer, "{}", BIGRAMS_MARKER)
+    }
+
+    pub fn write_bigram(&mut self, token1: &str, token2: &str, count: u64) -> io::Result<()> {
+        if !is_writable_token(token1) || !is_writable_token(token2) {
+            self.skipped_rows += 1;
+            return Ok(());
+        }
+        writeln!(self.writer, "{}\t{}\t{}", count, token1, token2)
+    }
+
+    /// Number of rows skipped so far because a token contained a tab or line break.
+    pub fn skipped_rows(&self) -> u64 {
+        self.skipped_rows
+    }
+
+    /// Copy rows previously written by a `rows_only` writer.
+    pub fn copy_rows(&mut self, rows: &mut impl io::Read) -> io::Result<u64> {
+        io::copy(rows, &mut self.writer)
+    }
+
+    /// The underlying writer, without writing the end marker. For `rows_only` writers.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        writeln!(self.writer)?;
+        writeln!(self.writer, "{}", END_MARKER)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn is_writable_token(token: &str) -> bool {
+    !token.contains(['\t', '\n', '\r'])
+}
+
+/// Which section of the file the reader is in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Unigrams,
+    Bigrams,
+    End,
+}
+
+/// Identifies a binary frequencies file, followed by `BINARY_FORMAT_VERSION`.
+const BINARY_MAGIC: &[u8; 4] = b"WFRQ";
+
+/// Version of the `BinaryFrequencies` schema. Bump it whenever the schema changes, old versions
+/// are then rejected rather than misread.
+const BINARY_FORMAT_VERSION: u8 = 1;
+
+/// Everything in a frequencies file, in the schema of binary frequencies files.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BinaryFrequencies {
+    pub total_unigrams: u64,
+    pub total_articles: Option<u64>,
+    pub unigrams: u64,
+    pub bigrams: u64,
+
+    /// Written unigram rows: token, count and article count if written with one.
+    pub unigram_rows: Vec<(String, u64, Option<u64>)>,
+
+    /// Written bigram rows: first token, second token and count.
+    pub bigram_rows: Vec<(String, String, u64)>,
+}
+
+impl BinaryFrequencies {
+    /// Read a whole text frequencies file.
+    pub fn from_text(input_file: &Path) -> Result<BinaryFrequencies, Box<dyn Error>> {
+        let reader = FrequenciesReader::open(input_file)?;
+        let header = reader.header().clone();
+        let mut result = BinaryFrequencies {
+            total_unigrams: header.total_unigrams,
+            total_articles: header.total_articles,
+            unigrams: header.unigrams,
+            bigrams: header.bigrams,
+            ..BinaryFrequencies::default()
+        };
+        for entry in reader {
+            match entry? {
+                FrequenciesEntry::Unigram(token, count, article_count) => {
+                    result.unigram_rows.push((token, count, article_count))
+                }
+                FrequenciesEntry::Bigram(token1, token2, count) => {
+                    result.bigram_rows.push((token1, token2, count))
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn write(&self, output_file: &Path) -> Result<(), Box<dyn Error>> {
+        let mut output = BufWriter::new(File::create(output_file)?);
+        output.write_all(BINARY_MAGIC)?;
+        output.write_all(&[BINARY_FORMAT_VERSION])?;
+        bincode::serialize_into(&mut output, self)?;
+        output.flush()?;
+        Ok(())
+    }
+
+    fn read(mut input: impl Read, description: &str) -> Result<BinaryFrequencies, Box<dyn Error>> {
+        let mut version = [0; 1];
+        input.read_exact(&mut version)?;
+        if version[0] != BINARY_FORMAT_VERSION {
+            let err: Box<dyn Error> = format!(
+                "Unsupported binary frequencies format version {} in {}, expected {}",
+                version[0], description, BINARY_FORMAT_VERSION
+            )
+            .into();
+            return Err(err);
+        }
+        Ok(bincode::deserialize_from(input)?)
+    }
+}
+
+/// Where a `FrequenciesReader` gets its rows from.
+enum Source {
+    Text(LineIterator),
+    Binary(std::vec::IntoIter<FrequenciesEntry>),
+}
+
+/// Reads a frequencies file, text or binary. The header is read by `open`, then iterating yields
+/// the unigram rows followed by the bigram rows.
+pub struct FrequenciesReader {
+    source: Source,
+    header: FrequenciesHeader,
+    section: Section,
+    line_number: usize,
+    description: String,
+}
+
+impl FrequenciesReader {
+    pub fn open(input_file: &Path) -> Result<FrequenciesReader, Box<dyn Error>> {
+        let description = format!("{:?}", input_file);
+        let mut input = BufReader::new(File::open(input_file)?);
+        if input.fill_buf()?.starts_with(BINARY_MAGIC) {
+            input.consume(BINARY_MAGIC.len());
+            let binary = BinaryFrequencies::read(input, &description)?;
+            return Ok(FrequenciesReader::from_binary(binary, description));
+        }
+        FrequenciesReader::new(LineIterator::new(input_file)?, description)
+    }
+
+    fn from_binary(binary: BinaryFrequencies, description: String) -> FrequenciesReader {
+        let unigrams = binary
+            .unigram_rows
+            .into_iter()
+            .map(|(token, count, article_count)| {
+                FrequenciesEntry::Unigram(token, count, article_count)
+            });
+        let bigrams = binary
+            .bigram_rows
+            .into_iter()
+            .map(|(token1, token2, count)| FrequenciesEntry::Bigram(token1, token2, count));
+        FrequenciesReader {
+            source: Source::Binary(unigrams.chain(bigrams).collect::<Vec<_>>().into_iter()),
+            header: FrequenciesHeader {
+                total_unigrams: binary.total_unigrams,
+                total_articles: binary.total_articles,
+                unigrams: binary.unigrams,
+                bigrams: binary.bigrams,
+            },
+            section: Section::Unigrams,
+            line_number: 0,
+            description,
+        }
+    }
+
+    /// Read an uncompressed frequencies file from `reader`. `description` names it in errors.
+    pub fn from_reader(
+        reader: Box<dyn BufRead>,
+        description: &str,
+    ) -> Result<FrequenciesReader, Box<dyn Error>> {
+        FrequenciesReader::new(LineIterator::from_reader(reader), description.to_string())
+    }
+
+    fn new(lines: LineIterator, description: String) -> Result<FrequenciesReader, Box<dyn Error>> {
+        let mut reader = FrequenciesReader {
+            source: Source::Text(lines),
+            header: FrequenciesHeader::default(),
+            section: Section::End,
+            line_number: 0,
+            description,
+        };
+        reader.read_header()?;
+        Ok(reader)
+    }
+
+    pub fn header(&self) -> &FrequenciesHeader {
+        &self.header
+    }
+
+    /// Only the unigram rows, stopping without reading the bigram section.
+    pub fn unigrams(self) -> impl Iterator<Item = io::Result<(String, u64)>> {
+        self.map_while(|entry| match entry {
+            Ok(FrequenciesEntry::Unigram(token, count, _article_count)) => Some(Ok((token, count))),
+            Ok(FrequenciesEntry::Bigram(..)) => None,
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    /// Only the bigram rows.
+    pub fn bigrams(self) -> impl Iterator<Item = io::Result<(String, String, u64)>> {
+        self.filter_map(|entry| match entry {
+            Ok(FrequenciesEntry::Unigram(..)) => None,
+            Ok(FrequenciesEntry::Bigram(token1, token2, count)) => {
+                Some(Ok((token1, token2, count)))
+            }
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    fn read_header(&mut self) -> Result<(), Box<dyn Error>> {
+        let lines = match &mut self.source {
+            Source::Text(lines) => lines,
+            Source::Binary(_) => return Ok(()),
+        };
+        let mut total_unigrams = None;
+        for line in lines.by_ref() {
+            let line = line?;
+            self.line_number += 1;
+            let line_borrowed = line.borrow();
+            let line_borrowed = line_borrowed.deref().trim_end();
+            if line_borrowed.starts_with(UNIGRAMS_MARKER) {
+                self.section = Section::Unigrams;
+                break;
+            }
+            if let Some((key, value)) = line_borrowed.split_once(" = ") {
+                let value = match value.parse::<u64>() {
+                    Ok(value) => value,
+                    Err(_) => return Err(self.malformed(line_borrowed).into()),
+                };
+                match key {
+                    TOTAL_UNIGRAMS_KEY => total_unigrams = Some(value),
+                    TOTAL_ARTICLES_KEY => self.header.total_articles = Some(value),
+                    UNIGRAMS_KEY => self.header.unigrams = value,
+                    BIGRAMS_KEY => self.header.bigrams = value,
+                    _ => {}
+                }
+            }
+        }
+        if self.section != Section::Unigrams {
+            let err: Box<dyn Error> =
+                format!("No '{}' section in {}", UNIGRAMS_MARKER, self.description).into();
+            return Err(err);
+        }
+        self.header.total_unigrams = total_unigrams.ok_or_else(|| -> Box<dyn Error> {
+            format!("No '{}' header in {}", TOTAL_UNIGRAMS_KEY, self.description).into()
+        })?;
+        Ok(())
+    }
+
+    fn malformed(&self, line: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Malformed line {} of {}: {:?}",
+                self.line_number, self.description, line
+            ),
+        )
+    }
+}
+
+impl Iterator for FrequenciesReader {
+    type Item = io::Result<FrequenciesEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lines = match &mut self.source {
+            Source::Text(lines) => lines,
+            Source::Binary(entries) => return entries.next().map(Ok),
+        };
+        while self.section != Section::End {
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(err)) => {
+                    self.section = Section::End;
+                    return Some(Err(io::Error::new(
+                        err.kind(),
+                        format!(
+                            "Could not read line {} of {}: {}",
+                            self.line_number + 1,
+                            self.description,
+                            err
+                        ),
+                    )));
+                }
+                None => {
+                    self.section = Section::End;
+                    break;
+                }
+            };
+            self.line_number += 1;
+            let line_borrowed = line.borrow();
+            let line_borrowed = line_borrowed.deref().trim_end();
+            if line_borrowed.is_empty() {
+                continue;
+            }
+            if line_borrowed.starts_with(BIGRAMS_MARKER) {
+                self.section = Section::Bigrams;
+                continue;
+            }
+            if line_borrowed.starts_with(END_MARKER) {
+                self.section = Section::End;
+                break;
+            }
+            let elems: Vec<&str> = line_borrowed.split('\t').collect();
+            let entry = match (self.section, elems.as_slice()) {
+                (Section::Unigrams, [count, token]) => count
+                    .parse()
+                    .map(|count| FrequenciesEntry::Unigram(token.to_string(), count, None)),
+                (Section::Unigrams, [count, token, article_count]) => {
+                    match (count.parse(), article_count.parse()) {
+                        (Ok(count), Ok(article_count)) => Ok(FrequenciesEntry::Unigram(
+                            token.to_string(),
+                            count,
+                            Some(article_count),
+                        )),
+                        (Err(err), _) | (_, Err(err)) => Err(err),
+                    }
+                }
+                (Section::Bigrams, [count, token1, token2]) => count.parse().map(|count| {
+                    FrequenciesEntry::Bigram(token1.to_string(), token2.to_string(), count)
+                }),
+                _ => return Some(Err(self.malformed(line_borrowed))),
+            };
+            return Some(entry.map_err(|_| self.malformed(line_borrowed)));
+        }
+        None
+    }
+}
+
+/// A bigram and its count.
+pub type BigramCount = ((String, String), u64);
+
+/// Load the `\2-grams:` section of a frequencies file, in file order. With `skip_oov` any bigram
+/// containing the OOV token is left out.
+pub fn load_bigrams(input_file: &Path, skip_oov: bool) -> Result<Vec<BigramCount>, Box<dyn Error>> {
+    let mut result = Vec::new();
+    for bigram in FrequenciesReader::open(input_file)?.bigrams() {
+        let (token1, token2, count) = bigram?;
+        if skip_oov && (token1 == OUT_OF_VOCABULARY_WORD || token2 == OUT_OF_VOCABULARY_WORD) {
+            continue;
+        }
+        result.push(((token1, token2), count));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_entries(contents: &[u8]) -> Vec<io::Result<FrequenciesEntry>> {
+        FrequenciesReader::from_reader(Box::new(io::Cursor::new(contents.to_vec())), "test")
+            .unwrap()
+            .collect()
+    }
+
+    #[test]
+    fn rows_with_a_tab_in_a_token_are_skipped() {
+        let header = FrequenciesHeader {
+            total_unigrams: 5,
+            total_articles: None,
+            unigrams: 2,
+            bigrams: 2,
+        };
+        let mut writer = FrequenciesWriter::new(Vec::new(), &header).unwrap();
+        writer.begin_unigrams().unwrap();
+        writer.write_unigram("the", 3).unwrap();
+        writer.write_unigram("bad\ttoken", 2).unwrap();
+        writer.begin_bigrams().unwrap();
+        writer.write_bigram("the", "the", 1).unwrap();
+        writer.write_bigram("the", "bad\ttoken", 1).unwrap();
+        assert_eq!(writer.skipped_rows(), 2);
+        let contents = writer.finish().unwrap();
+
+        let entries: Vec<FrequenciesEntry> = read_entries(&contents)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                FrequenciesEntry::Unigram(String::from("the"), 3, None),
+                FrequenciesEntry::Bigram(String::from("the"), String::from("the"), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_bigram_rows_are_errors_with_their_line_number() {
+        let header = concat!(
+            "\\data\\\n",
+            "total unigrams = 3\n",
+            "ngram 1 = 1\n",
+            "ngram 2 = 1\n",
+            "\n",
+            "\\1-grams:\n",
+            "3\tthe\n",
+            "\n",
+            "\\2-grams:\n",
+        );
+        for row in &["1\tthe", "x\tthe\tthe"] {
+            let contents = format!("{}{}\n\n\\end\\\n", header, row);
+            let entries = read_entries(contents.as_bytes());
+            assert_eq!(entries.len(), 2, "{:?}", row);
+            assert!(entries[0].is_ok());
+            let err = entries[1].as_ref().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                err.to_string(),
+                format!("Malformed line 10 of test: {:?}", row)
+            );
+        }
+    }
+}
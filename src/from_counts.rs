@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufWriter};
+use std::path::Path;
+
+use flate2::{Compression, GzBuilder};
+use log::info;
+
+use crate::frequencies::{FrequenciesHeader, FrequenciesWriter};
+use crate::util::{gzip_member_filename, open_input, InputCompression};
+
+/// Write a GZIP-compressed frequencies file with only unigrams from a `word\tcount` TSV file, e.g.
+/// counts made by another tool, so that the other sub-commands can read them. `total unigrams` is
+/// the sum of the counts. The TSV may be GZIP compressed, blank lines are ignored and the counts
+/// of a word listed more than once are summed. Errors on the first line that isn't a non-empty
+/// word, a tab and a non-negative integer count.
+pub fn handle_from_counts(input_file: &Path, output_file: &Path) -> Result<(), Box<dyn Error>> {
+    info!("reading counts from {:?}...", input_file);
+    let (reader, _bytes_read) = open_input(input_file, InputCompression::Auto)?;
+    let mut unigram_counts: BTreeMap<String, u64> = BTreeMap::new();
+    let mut total_unigrams: u64 = 0;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (word, count) = parse_count_line(&line).map_err(|err| {
+            format!(
+                "Invalid line {} of {:?}, {}: {:?}",
+                i + 1,
+                input_file,
+                err,
+                line
+            )
+        })?;
+        total_unigrams = total_unigrams
+            .checked_add(count)
+            .ok_or("Total count overflows a 64-bit integer")?;
+        *unigram_counts.entry(word.to_string()).or_insert(0) += count;
+    }
+
+    info!(
+        "writing {} unigrams to {:?}...",
+        unigram_counts.len(),
+        output_file
+    );
+    let file = File::create(output_file).map_err(|err| {
+        format!(
+            "Could not create output file {:?} due to {:?}",
+            output_file, err
+        )
+    })?;
+    let output = BufWriter::new(
+        GzBuilder::new()
+            .filename(gzip_member_filename(output_file))
+            .write(file, Compression::best()),
+    );
+    let header = FrequenciesHeader {
+        total_unigrams,
+        total_articles: None,
+        unigrams: unigram_counts.len() as u64,
+        bigrams: 0,
+    };
+    let mut output = FrequenciesWriter::new(output, &header)?;
+    output.begin_unigrams()?;
+    for (token, count) in unigram_counts.iter() {
+        output.write_unigram(token, *count)?;
+    }
+    output.begin_bigrams()?;
+    output
+        .finish()?
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .finish()?;
+
+    Ok(())
+}
+
+/// Split a `word\tcount` line into its word and count.
+fn parse_count_line(line: &str) -> Result<(&str, u64), &'static str> {
+    let (word, count) = line
+        .split_once('\t')
+        .ok_or("expected a word and a count separated by a tab")?;
+    let word = word.trim();
+    if word.is_empty() {
+        return Err("the word is empty");
+    }
+    if word.contains(char::is_whitespace) {
+        return Err("the word contains whitespace");
+    }
+    let count = count
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| "the count is not a non-negative integer")?;
+    Ok((word, count))
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::frequencies::load_bigrams;
+use crate::topkwords::load_sorted_onegrams;
+
+/// Generate a line of random text by sampling from the counts in a frequencies file. In bigram
+/// mode each word is sampled conditioned on the previous word, falling back to the unigram
+/// distribution when the previous word has no observed successors.
+pub fn handle_generate(
+    input_file: &Path,
+    number_of_words: usize,
+    use_bigrams: bool,
+    seed: u64,
+) -> Result<(), Box<dyn Error>> {
+    let unigrams = load_sorted_onegrams(input_file)?;
+    let mut bigrams: HashMap<String, Counts> = HashMap::new();
+    if use_bigrams {
+        for ((word1, word2), count) in load_bigrams(input_file, true)? {
+            bigrams.entry(word1).or_default().push((word2, count));
+        }
+    }
+    if unigrams.is_empty() {
+        let err: Box<dyn Error> = format!("No unigrams to sample from in {:?}", input_file).into();
+        return Err(err);
+    }
+    let unigram_distribution = WeightedIndex::new(unigrams.iter().map(|(_word, count)| *count))?;
+    let mut bigram_distributions = HashMap::with_capacity(bigrams.len());
+    for (word, successors) in bigrams.iter() {
+        let distribution = WeightedIndex::new(successors.iter().map(|(_word, count)| *count))?;
+        bigram_distributions.insert(word.as_str(), (successors, distribution));
+    }
+
+    let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+    let mut words: Vec<&str> = Vec::with_capacity(number_of_words);
+    for _ in 0..number_of_words {
+        let previous = words.last().and_then(|word| bigram_distributions.get(word));
+        let word = match previous {
+            Some((successors, distribution)) => {
+                successors[distribution.sample(&mut rng)].0.as_str()
+            }
+            None => unigrams[unigram_distribution.sample(&mut rng)].0.as_str(),
+        };
+        words.push(word);
+    }
+    println!("{}", words.join(" "));
+
+    Ok(())
+}
+
+/// Words and their counts, the successors of one word.
+type Counts = Vec<(String, u64)>;
@@ -0,0 +1,21 @@
+//! The sub-commands of the `word-frequencies` binary, also exposed as a library so that the
+//! parsers can be fuzzed, see `fuzz/`.
+
+pub mod create_frequencies;
+pub mod dict_info;
+pub mod external_sort;
+pub mod fixtures;
+pub mod frequencies;
+pub mod from_counts;
+pub mod generate;
+pub mod merge;
+pub mod pmi;
+pub mod probabilities;
+pub mod run_log;
+pub mod sample;
+pub mod serve;
+pub mod split;
+pub mod stats;
+pub mod topkwords;
+pub mod transition_matrix;
+pub mod util;
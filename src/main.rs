@@ -1,20 +1,56 @@
 extern crate clap;
 
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, Shell, SubCommand};
+use encoding_rs::Encoding;
+use log::{error, Level, LevelFilter};
+use serde_json::json;
+use std::env;
 use std::error::Error;
-use std::path::Path;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
 
-pub mod create_frequencies;
-pub mod split;
-pub mod topkwords;
-pub mod util;
+use word_frequencies::{
+    create_frequencies, dict_info, fixtures, from_counts, generate, merge, pmi, probabilities,
+    run_log, sample, serve, split, stats, topkwords, transition_matrix, util,
+};
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let app = App::new("Word frequency counter using Wikipedia dataset dumps.")
+fn main() {
+    if let Err(err) = run() {
+        error!("{}", err);
+        process::exit(1);
+    }
+}
+
+/// The command-line interface, shared by argument parsing and `completions`. Sub-commands for
+/// developing this tool are hidden unless `show_hidden`, which `--help-hidden` sets.
+fn app(show_hidden: bool) -> App<'static, 'static> {
+    let developer_setting = if show_hidden {
+        AppSettings::ColoredHelp
+    } else {
+        AppSettings::Hidden
+    };
+    App::new("Word frequency counter using Wikipedia dataset dumps.")
         .setting(AppSettings::ArgRequiredElseHelp)
+        .arg(
+            Arg::with_name("help_hidden")
+                .long("help-hidden")
+                .hidden(true)
+                .help("Print help including the sub-commands for developing this tool."),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .multiple(true)
+                .global(true)
+                .help("Log more detail, repeat for even more. Progress is logged by default, RUST_LOG overrides the level."),
+        )
         .subcommand(
             SubCommand::with_name("split")
                 .about("Split a cirrussearch JSON GZ file into pieces")
+                .arg(stats_file_arg())
                 .arg(
                     Arg::with_name("input_path")
                         .long("input-path")
@@ -22,7 +58,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .required(true)
                         .takes_value(true)
                         .validator(input_path_is_file)
-                        .help("Path to cirrussearch JSON GZ file, download from https://dumps.wikimedia.org/other/cirrussearch/")
+                        .help("Path to cirrussearch JSON GZ (or uncompressed JSON lines) file, download from https://dumps.wikimedia.org/other/cirrussearch/")
                         .value_name("FILE"),
                 )
                 .arg(
@@ -41,13 +77,100 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .required(false)
                         .takes_value(true)
                         .validator(validate_pieces)
-                        .default_value("12")
-                        .help("How many pieces to split the input file into.")
+                        .help("How many pieces to split the input file into. [default: 12]")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("chunk_lines")
+                        .long("chunk-lines")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_lines)
+                        .conflicts_with_all(&["pieces", "resume"])
+                        .help("Instead of a fixed number of pieces, write the articles in input order to pieces of this many lines each, as many pieces as needed, e.g. 100000. Pieces are named as usual, so create-frequencies counts them the same way. Such a split isn't checkpointed, so it can't be resumed.")
+                        .value_name("LINES"),
+                )
+                .arg(
+                    Arg::with_name("preserve_order")
+                        .long("preserve-order")
+                        .required(false)
+                        .takes_value(false)
+                        .conflicts_with_all(&["chunk_lines", "end_line"])
+                        .help("Write the articles to the pieces in contiguous blocks in input order, roughly the first 1/N of the input by compressed size to the first piece and so on, rather than each to a random piece. Pieces then hold neighbouring articles, e.g. to narrow down a problem to part of the dump, but are no longer random samples of it. Pass it again when resuming."),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Continue an interrupted split from its last checkpoint instead of starting over. Errors if the input file, its size or any option that decides which lines go to which piece differs from the interrupted split's."),
+                )
+                .arg(
+                    Arg::with_name("input_compression")
+                        .long("input-compression")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["auto", "gzip", "bzip2", "none"])
+                        .default_value("auto")
+                        .help("Compression of the input file. 'auto' picks gzip for .gz, bzip2 for .bz2, none for .json/.jsonl/.ndjson, and otherwise checks the file contents."),
+                )
+                .arg(
+                    Arg::with_name("single_file")
+                        .long("single-file")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Combine the pieces into one BASENAME.split.gz archive, with an index of where each piece is in it, instead of leaving one file per piece. 'create-frequencies' still counts the pieces in parallel."),
+                )
+                .arg(
+                    Arg::with_name("exclude_ids_file")
+                        .long("exclude-ids-file")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("File of page IDs, one per line, whose records to leave out, e.g. the articles of a test set so that they don't leak into the frequencies. IDs are matched against the '_id' of the index line before each record. The number of records excluded is logged.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("json_pointer")
+                        .long("json-pointer")
+                        .required(false)
+                        .takes_value(true)
+                        .default_value(split::DEFAULT_TEXT_POINTER)
+                        .validator(validate_json_pointer)
+                        .help("Where the article text is in each record, in JSON pointer syntax, e.g. '/source_text' or '/heading/0', for dumps with a different schema. A field that is an array of strings, e.g. of paragraphs, is joined by spaces. Records where it points to nothing or to something other than a string or an array of strings are skipped.")
+                        .value_name("POINTER"),
+                )
+                .arg(
+                    Arg::with_name("skip_redirects")
+                        .long("skip-redirects")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Skip records that aren't articles: those whose 'namespace' isn't 0, e.g. talk, user or category pages, and those without text, such as redirects. Improves the quality of the counts for dumps that include other namespaces. The number skipped is logged.")
+                )
+                .arg(
+                    Arg::with_name("start_line")
+                        .long("start-line")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_line_number)
+                        .conflicts_with("resume")
+                        .help("Only split input lines from this one on, counting from 1 and including index lines. Lines are then assigned to different pieces than in a full split.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("end_line")
+                        .long("end-line")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_line_number)
+                        .conflicts_with("resume")
+                        .help("Stop splitting after this input line, inclusive.")
                         .value_name("POSITIVE INTEGER"),
                 ))
         .subcommand(
             SubCommand::with_name("create-frequencies")
                 .about("Create a frequencies file from line-delimited files of articles")
+                .arg(stats_file_arg())
                 .arg(
                     Arg::with_name("input_dir")
                         .long("input-dir")
@@ -64,9 +187,15 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .short("o")
                         .required(true)
                         .takes_value(true)
-                        .help("Name of output ARPA language model file. Will be GZIP compressed and have .gz appended.")
+                        .help("Name of output ARPA language model file. Will be GZIP compressed and have .gz appended. Refuses to overwrite an existing file unless --force is passed. '-' writes the compressed file to stdout instead, for piping, and nothing to DIR; reports that are otherwise printed then go to stderr.")
                         .value_name("FILE"),
-
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Overwrite the output file, and any sidecar files, if they already exist."),
                 )
                 .arg(
                     Arg::with_name("language")
@@ -74,14 +203,335 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .short("l")
                         .required(true)
                         .takes_value(true)
-                        .validator(validate_language_code)
-                        .help("Two-character language code for dictionary, e.g. en, pl, etc.")
+                        .validator(validate_language_codes)
+                        .help("Two-character language code for dictionary, e.g. en, pl, etc. Pass a comma-separated list, e.g. en,pl, to combine dictionaries.")
                         .value_name("ISO 639-1 CODE"),
 
+                )
+                .arg(
+                    Arg::with_name("external_sort")
+                        .long("external-sort")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Spill per-file counts to sorted temporary files and merge them while writing, instead of holding all counts in memory."),
+                )
+                .arg(
+                    Arg::with_name("drop_oov")
+                        .long("drop-oov")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Leave the out-of-vocabulary token <unk> and any bigram containing it out of the output. OOV tokens still count towards 'total unigrams'."),
+                )
+                .arg(
+                    Arg::with_name("exclude_oov_from_total")
+                        .long("exclude-oov-from-total")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Leave OOV tokens out of 'total unigrams', so unigram probabilities are conditioned on the word being in the dictionary."),
+                )
+                .arg(
+                    Arg::with_name("max_tokens_per_line")
+                        .long("max-tokens-per-line")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_max_tokens_per_line)
+                        .default_value("1000000")
+                        .help("Lines with more tokens than this are truncated, protecting against malformed input.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("fold_dictionary_case")
+                        .long("fold-dictionary-case")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Lowercase dictionary entries and match tokens against the dictionary case-insensitively. Tokens are still counted in their original case."),
+                )
+                .arg(
+                    Arg::with_name("split_hyphens")
+                        .long("split-hyphens")
+                        .required(false)
+                        .takes_value(false)
+                        .help("As well as the whole hyphenated token, count each of its components that is in the dictionary as a unigram. Each component adds to 'total unigrams'."),
+                )
+                .arg(
+                    Arg::with_name("assume_normalized")
+                        .long("assume-normalized")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Skip NFKC normalization of the input, which is only safe when it was produced by 'split' or is otherwise already NFKC-normalized. Saves a per-character check on every line."),
+                )
+                .arg(
+                    Arg::with_name("strip_accents")
+                        .long("strip-accents")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Count accented letters as their base letter, e.g. 'café' as 'cafe', in the input and the dictionary alike. Words that only differ in accents are merged, shrinking the vocabulary. Inappropriate for languages where accents distinguish letters or words, e.g. Polish 'łączę' vs 'łacze', or for scripts such as Devanagari whose vowel signs are combining marks."),
+                )
+                .arg(
+                    Arg::with_name("max_memory")
+                        .long("max-memory")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_max_memory)
+                        .help("Soft limit on the estimated memory used for counts, split evenly between threads. When a thread exceeds its share it flushes its counts to disk. Implies --external-sort.")
+                        .value_name("MEGABYTES"),
+                )
+                .arg(
+                    Arg::with_name("article_counts")
+                        .long("article-counts")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Also write the number of articles each word is in as a third column of the unigram rows, needed for 'top-k-words --idf'."),
+                )
+                .arg(
+                    Arg::with_name("two_pass")
+                        .long("two-pass")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Read the input twice, first counting only the articles each word is in, so that bigrams with words that won't be written are never held in memory. The 'ngram 2' header then only counts the bigrams that were kept."),
+                )
+                .arg(
+                    Arg::with_name("sample_oov")
+                        .long("sample-oov")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_lines)
+                        .help("Dry run: tokenize only the first this many lines of the input files, report the OOV rate against the dictionary and exit without writing an output file.")
+                        .value_name("LINES"),
+                )
+                .arg(
+                    Arg::with_name("report_top_oov")
+                        .long("report-top-oov")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_report_top_oov)
+                        .conflicts_with_all(&["external_sort", "max_memory"])
+                        .help("Also count the words that are counted as <unk> because they aren't in the dictionary, and print the N most frequent at the end, the words most worth adding to it. Works with --sample-oov too. At least 100 times N distinct words are kept, so the counts are exact unless there are more.")
+                        .value_name("N"),
+                )
+                .arg(
+                    Arg::with_name("keep_going")
+                        .long("keep-going")
+                        .short("k")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Skip input files that fail to be counted, with a warning, rather than aborting. The output then only covers the other files."),
+                )
+                .arg(
+                    Arg::with_name("length_normalize")
+                        .long("length-normalize")
+                        .required(false)
+                        .takes_value(false)
+                        .conflicts_with_all(&["external_sort", "max_memory"])
+                        .help("Weight every token of an article by 1 / sqrt(article length in tokens), so that long articles don't dominate the counts. Counts are then sums of weights, rounded when written and at least 1, not numbers of occurrences. Article counts are not weighted."),
+                )
+                .arg(
+                    Arg::with_name("window")
+                        .long("window")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_window)
+                        .default_value("1")
+                        .help("Count each word as a bigram with each of the N words before it, i.e. co-occurrences within a window, rather than only with the word right before it. 1 counts ordinary bigrams.")
+                        .value_name("N"),
+                )
+                .arg(
+                    Arg::with_name("distance_weighting")
+                        .long("distance-weighting")
+                        .required(false)
+                        .takes_value(false)
+                        .conflicts_with_all(&["external_sort", "max_memory"])
+                        .help("Weight a bigram of words that are D apart within the --window by 1 / D rather than 1, as GloVe does. Bigram counts are then sums of weights, rounded when written and at least 1."),
+                )
+                .arg(
+                    Arg::with_name("dedup_bigrams_per_article")
+                        .long("dedup-bigrams-per-article")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Count each distinct bigram at most once per article (line), so bigram counts are the number of articles a bigram is in rather than how often it occurs. This keeps phrasing repeated by infoboxes and navigation templates from dominating the bigram counts. Unigram counts are unchanged."),
+                )
+                .arg(
+                    Arg::with_name("max_vocab")
+                        .long("max-vocab")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_max_vocab)
+                        .conflicts_with_all(&["two_pass", "external_sort", "max_memory"])
+                        .help("Keep only the N most frequent words, instead of those in more than 40 articles, and count every other word as <unk>, for toolkits that expect a fixed-size vocabulary. Logs how much of the unigram mass was folded into <unk>.")
+                        .value_name("N"),
+                )
+                .arg(
+                    Arg::with_name("largest_first")
+                        .long("largest-first")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Count the largest input files first rather than in order of name, e.g. when pieces are of uneven size."),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .long("threads")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_threads)
+                        .help("Number of threads that count input files, one per file at a time. [default: the number of CPUs less one, at least 1]")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("deterministic")
+                        .long("deterministic")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Merge the counts of the input files in input order rather than in the order they finish. Only fractional weights, --vocabulary-growth and --report-top-oov depend on that order; with --threads 1 the output is then reproducible run to run, e.g. to compare two versions of this tool."),
+                )
+                .arg(
+                    Arg::with_name("cache_counts")
+                        .long("cache-counts")
+                        .required(false)
+                        .takes_value(true)
+                        .conflicts_with_all(&["external_sort", "max_memory", "sample_oov"])
+                        .help("Save the counts to this file, and on later runs with the same input files and counting options load them from it instead of counting again, so that changing only how they're written, e.g. --max-vocab, --article-counts or --format, is quick. Changed input files, by size or modification time, are counted again. Keep it outside the input directory.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("vocabulary_growth")
+                        .long("vocabulary-growth")
+                        .required(false)
+                        .takes_value(false)
+                        .conflicts_with_all(&["external_sort", "max_memory"])
+                        .help("Also write the vocabulary growth curve, for Heaps' law analyses, to a sidecar OUTPUT_FILE.growth.gz of article_number<TAB>cumulative_vocab_size rows, one per article. Holds each article's new words in memory until the end."),
+                )
+                .arg(
+                    Arg::with_name("track_case")
+                        .long("track-case")
+                        .required(false)
+                        .takes_value(false)
+                        .conflicts_with_all(&["external_sort", "max_memory"])
+                        .help("Also count how often each capitalization of every word occurs, e.g. 'apple', 'Apple' and 'APPLE', and write them as word<TAB>form<TAB>count rows, the word lowercased, to a sidecar OUTPUT_FILE.case.gz. Uses up to as much memory again as the unigram counts."),
+                )
+                .arg(
+                    Arg::with_name("article_boundaries")
+                        .long("article-boundaries")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Put a start and an end token around the tokens of every article, counting the bigrams of the first and last word with them. Single-word articles are then counted too."),
+                )
+                .arg(
+                    Arg::with_name("boundary_tokens")
+                        .long("boundary-tokens")
+                        .required(false)
+                        .takes_value(true)
+                        .requires("article_boundaries")
+                        .validator(validate_boundary_tokens)
+                        .help("Start and end tokens for --article-boundaries, comma-separated. They are never mapped to <unk>. [default: <s>,</s>]")
+                        .value_name("START,END"),
+                )
+                .arg(
+                    Arg::with_name("number_class")
+                        .long("number-class")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_number_class)
+                        .help("Count every number, digits optionally with '.' and ',' such as 1984, 3.14 or 1,000, as this token instead, e.g. '<num>', so that 'in 1984' and 'in 2001' are both counted as 'in <num>'. The token is never mapped to <unk>. Tokens that only contain digits, such as 1990s, are left alone.")
+                        .value_name("TOKEN"),
+                )
+                .arg(
+                    Arg::with_name("script")
+                        .long("script")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["latin", "greek", "cyrillic", "hebrew", "arabic", "han"])
+                        .help("Skip tokens with letters outside of this writing system before counting, as if they weren't in the text, e.g. Cyrillic or CJK fragments in an English corpus. Digits, punctuation and other characters common to every script are allowed. Skipped tokens aren't counted as <unk>, and the words around them count as a bigram. [default: every token is counted]")
+                        .value_name("SCRIPT"),
+                )
+                .arg(
+                    Arg::with_name("report_memory")
+                        .long("report-memory")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Every 100,000 lines of each input file, and after merging, log the estimated size of the counts and the peak RSS. Useful to pick a thread count or decide whether --external-sort is needed."),
+                )
+                .arg(
+                    Arg::with_name("trim_chars")
+                        .long("trim-chars")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_trim_chars)
+                        .help("Trim only these characters, and whitespace, from the ends of tokens and dictionary entries instead of all ASCII punctuation, e.g. '.,;:!?\"“”'.")
+                        .value_name("CHARS"),
+                )
+                .arg(
+                    Arg::with_name("keep_chars")
+                        .long("keep-chars")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_trim_chars)
+                        .help("Never trim these characters from the ends of tokens and dictionary entries, e.g. \"'\" to keep the trailing apostrophe of words like Jones'.")
+                        .value_name("CHARS"),
+                )
+                .arg(
+                    Arg::with_name("unit")
+                        .long("unit")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["word", "byte"])
+                        .default_value("word")
+                        .help("What to count. 'byte' counts the raw bytes of each line, written as two hex digits, without decoding, normalization or the dictionary, so 'total unigrams' is the number of bytes. Options about words and the dictionary then have no effect, and --max-tokens-per-line limits bytes.")
+                        .value_name("UNIT"),
+                )
+                .arg(
+                    Arg::with_name("input_encoding")
+                        .long("input-encoding")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_input_encoding)
+                        .help("Encoding of the input files, e.g. ISO-8859-2 or windows-1250 for older Polish text, decoded to UTF-8 before counting. Any ASCII-compatible encoding label from the WHATWG Encoding Standard works. Bytes that aren't valid in the encoding become U+FFFD. [default: UTF-8, which must then be valid]")
+                        .value_name("ENCODING"),
+                )
+                .arg(
+                    Arg::with_name("sort_stable")
+                        .long("sort-stable")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Explicitly sort the unigram and bigram rows by their words, byte by byte, so that files from different dumps or versions of this tool diff cleanly. Rows are already written in this order, but without this that is an implementation detail."),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["text", "bincode"])
+                        .default_value("text")
+                        .help("Format of the output file. 'bincode' writes OUTPUT_FILE.bin instead of OUTPUT_FILE.gz, a versioned binary file that loads much faster and that the other subcommands read like the text format."),
+                )
+                .arg(
+                    Arg::with_name("pretty")
+                        .long("pretty")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Also write OUTPUT_FILE.pretty.txt, the output with aligned columns, section headings and thousands separators, for inspecting by eye. It can't be read back by the other sub-commands; the output file itself is unchanged."),
+                )
+                .arg(
+                    Arg::with_name("input_json")
+                        .long("input-json")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Count raw cirrussearch dumps without splitting them first: the input files are the files in INPUT_DIR with '.json' in their name, e.g. '*.json.gz', and every line is parsed as a JSON record whose --json-fields are counted. Index lines and records without any of the fields aren't articles. Parsing JSON in the counting loop is a good deal slower than reading split files, and each file is counted on one thread, so split a large dump if it is counted more than once. Has no effect with '--unit byte'."),
+                )
+                .arg(
+                    Arg::with_name("json_fields")
+                        .long("json-fields")
+                        .required(false)
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .requires("input_json")
+                        .validator(validate_json_pointer)
+                        .help("Comma-separated JSON pointers to the fields of each record to count with --input-json, joined by spaces in this order, e.g. '/title,/text'. Arrays of strings are joined by spaces too. Fields that are missing or neither are left out. [default: /text]")
+                        .value_name("POINTERS"),
                 ))
         .subcommand(
             SubCommand::with_name("top-k-words")
                 .about("Create a file with the top K words (unigrams) in a frequencies file")
+                .arg(stats_file_arg())
                 .arg(
                     Arg::with_name("input_file")
                         .long("input-file")
@@ -98,33 +548,649 @@ fn main() -> Result<(), Box<dyn Error>> {
                         .short("o")
                         .required(true)
                         .takes_value(true)
-                        .help("Name of output file to put top K words. Will not be compressed.")
-                        .value_name("FILE"),
+                        .help("Name of output file to put top K words. Will not be compressed. Refuses to overwrite an existing file unless --force is passed.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Overwrite the output file if it already exists."),
+                )
+                .arg(
+                    Arg::with_name("number_of_words")
+                        .long("number-of-words")
+                        .short("k")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_number_of_words)
+                        .default_value("10000")
+                        .help("Number of words to return, starting with most frequent.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("minimum_word_length")
+                        .long("minimum-word-length")
+                        .short("m")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_minimum_word_length)
+                        .default_value("3")
+                        .help("Minimum (inclusive) length of word to consider.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("min_lengths")
+                        .long("min-lengths")
+                        .required(false)
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .validator(validate_minimum_word_length)
+                        .help("Comma-separated minimum word lengths to write one output file each for, e.g. '3,4,5', instead of --minimum-word-length. The files are named after OUTPUT_FILE with '.minN' before its extension, e.g. 'out.min3.txt'. Words are loaded and ranked once for all of them, which is much faster than one run per length.")
+                        .value_name("LENGTHS"),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["frequency", "alpha"])
+                        .default_value("frequency")
+                        .help("Order of the written words. The top K words are always chosen by frequency, 'alpha' then writes them alphabetically."),
+                )
+                .arg(
+                    Arg::with_name("stable_against")
+                        .long("stable-against")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("A previous word list, e.g. from an older frequencies file, whose order to keep so that word IDs, i.e. line numbers, stay stable: words still in the top K are written first in their previous order, then the words new to it by frequency. Words that dropped out are removed. Cannot be used with --sort alpha. The word is the first tab-separated field of each line, or the second if the lines start with ranks counting up by 1, as with --with-rank. How many words were kept, added and removed is logged, which ones with -v.")
+                        .value_name("EXISTING"),
+                )
+                .arg(
+                    Arg::with_name("include_ties")
+                        .long("include-ties")
+                        .required(false)
+                        .takes_value(false)
+                        .conflicts_with("priors")
+                        .help("Also write the words with the same count as the K-th word, rather than cutting the list off in the middle of a tie, so that of two equally frequent words either both or neither are written. The number of words written is logged when it exceeds K."),
+                )
+                .arg(
+                    Arg::with_name("with_rank")
+                        .long("with-rank")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Start each line with the word's 1-based rank by frequency and a tab, e.g. '1\\tthe', also with --sort alpha and before any other column. With --stable-against it is the line number instead, so that it stays stable too."),
+                )
+                .arg(
+                    Arg::with_name("id_width")
+                        .long("id-width")
+                        .required(false)
+                        .takes_value(true)
+                        .requires("with_rank")
+                        .validator(validate_id_width)
+                        .help("Zero-pad the ranks of --with-rank to this many digits, e.g. '00001\\tthe' for 5, for systems that expect a fixed-width number. Errors if the largest rank has more digits. The ranks are by frequency and change between runs, use --stable-against for stable IDs. [default: no padding]")
+                        .value_name("DIGITS"),
+                )
+                .arg(
+                    Arg::with_name("id_base")
+                        .long("id-base")
+                        .required(false)
+                        .takes_value(true)
+                        .requires("with_rank")
+                        .validator(validate_id_base)
+                        .help("Number the ranks of --with-rank from this rather than 1, e.g. 0 for 0-based ranks, or past the numbers of special tokens. [default: 1]")
+                        .value_name("NON-NEGATIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("language")
+                        .long("language")
+                        .short("l")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_language_code)
+                        .help("Language whose alphabet --sort alpha follows, e.g. 'pl' to sort 'ł' right after 'l' rather than after 'z'. Accents other than those of letters of their own only break ties. Languages without a collation, and the default, sort by Unicode code point.")
+                        .value_name("ISO 639-1 CODE"),
+                )
+                .arg(
+                    Arg::with_name("per_million")
+                        .long("per-million")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Write each word with its frequency per million words, tab-separated, instead of the word alone."),
+                )
+                .arg(
+                    Arg::with_name("idf")
+                        .long("idf")
+                        .required(false)
+                        .takes_value(false)
+                        .conflicts_with("per_million")
+                        .help("Write each word with its inverse document frequency, ln(total articles / articles containing the word), tab-separated. Needs a file created with 'create-frequencies --article-counts'."),
+                )
+                .arg(
+                    Arg::with_name("burstiness")
+                        .long("burstiness")
+                        .required(false)
+                        .takes_value(false)
+                        .conflicts_with_all(&["per_million", "idf"])
+                        .help("Write each word with its count, its article count and their ratio, the average occurrences per article it is in, tab-separated. High ratios mark topic-specific words that cluster in few articles, ratios near 1 words spread evenly. Needs a file created with 'create-frequencies --article-counts'."),
+                )
+                .arg(
+                    Arg::with_name("lowercase_output")
+                        .long("lowercase-output")
+                        .required(false)
+                        .takes_value(false)
+                        .conflicts_with_all(&["idf", "burstiness"])
+                        .help("Lowercase the words, summing the counts of words that are the same once lowercased, e.g. 'The' and 'the', before picking the top K. Can't be combined with --idf, as article counts can't be summed without double counting articles containing both."),
+                )
+                .arg(
+                    Arg::with_name("priors")
+                        .long("priors")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("Dictionary of prior weights, one word or word<TAB>weight per line, e.g. frequencies from a reference corpus, to blend with the corpus frequency when picking the top K, so that words that matter rank higher even if rare in the corpus. Words without a weight have a weight of 1, words not listed a prior of 0.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("prior_blend")
+                        .long("prior-blend")
+                        .required(false)
+                        .takes_value(true)
+                        .requires("priors")
+                        .validator(validate_prior_blend)
+                        .help("How much the prior counts when ranking: the score of a word is (1 - BLEND) times its corpus probability plus BLEND times its prior probability, its weight divided by the sum of the weights. Defaults to 0.5.")
+                        .value_name("BLEND"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("dict-info")
+                .about("Report statistics about the embedded dictionary for a language")
+                .arg(
+                    Arg::with_name("language")
+                        .long("language")
+                        .short("l")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(validate_language_code)
+                        .help("Two-character language code for dictionary, e.g. en, pl, etc.")
+                        .value_name("ISO 639-1 CODE"),
+                )
+                .arg(
+                    Arg::with_name("fold_case")
+                        .long("fold-case")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Lowercase dictionary entries and report how many merged."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("dict-diff")
+                .about("Compare the words of a frequencies file with the embedded dictionary for a language")
+                .arg(
+                    Arg::with_name("input_file")
+                        .long("input-file")
+                        .short("f")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("Frequencies file to compare, usually GZIP compressed.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("language")
+                        .long("language")
+                        .short("l")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(validate_language_code)
+                        .help("Two-character language code for dictionary, e.g. en, pl, etc.")
+                        .value_name("ISO 639-1 CODE"),
+                )
+                .arg(
+                    Arg::with_name("fold_case")
+                        .long("fold-case")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Compare lowercased words, as create-frequencies --fold-dictionary-case does."),
+                )
+                .arg(
+                    Arg::with_name("top_missing")
+                        .long("top-missing")
+                        .short("n")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_number_of_words)
+                        .default_value("100")
+                        .help("How many of the most frequent words that aren't in the dictionary to list. Files made by create-frequencies with the same dictionary have none, they are counted as <unk>; use create-frequencies --report-top-oov for those.")
+                        .value_name("POSITIVE INTEGER"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("generate")
+                .about("Generate random text by sampling words from a frequencies file")
+                .arg(
+                    Arg::with_name("input_file")
+                        .long("input-file")
+                        .short("f")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("GZIP-compressed frequencies file as produced by the 'create-frequencies' sub-command")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("words")
+                        .long("words")
+                        .short("n")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_words)
+                        .default_value("20")
+                        .help("Number of words to generate.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("mode")
+                        .long("mode")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["unigram", "bigram"])
+                        .default_value("unigram")
+                        .help("Sample words independently by unigram frequency, or from a bigram Markov chain conditioned on the previous word."),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_seed)
+                        .default_value("42")
+                        .help("Seed for the random number generator, the same seed gives the same output.")
+                        .value_name("INTEGER"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Print statistics about a frequencies file")
+                .arg(
+                    Arg::with_name("input_file")
+                        .long("input-file")
+                        .short("f")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("GZIP-compressed frequencies file as produced by the 'create-frequencies' sub-command")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("confidence_interval")
+                        .long("confidence-interval")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["90", "95", "99"])
+                        .help("Also print each unigram's probability with a Wilson score confidence interval at this percentage.")
+                        .value_name("PERCENT"),
+                )
+                .arg(
+                    Arg::with_name("following_entropy")
+                        .long("following-entropy")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_words)
+                        .help("Also print this many words with the highest and the lowest entropy of the following word, computed from the bigram counts.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("distinct_successors")
+                        .long("distinct-successors")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_words)
+                        .help("Also print, for this many of the most frequent words, how many distinct words follow them in the bigram counts, and their count, most distinct successors first. Function words have many successors, words with fixed continuations few.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("per_million")
+                        .long("per-million")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Also print each unigram's frequency per million words."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pmi")
+                .about("Print the bigrams whose words are most strongly associated, by pointwise mutual information")
+                .arg(
+                    Arg::with_name("input_file")
+                        .long("input-file")
+                        .short("f")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("GZIP-compressed frequencies file as produced by the 'create-frequencies' sub-command")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("min_count")
+                        .long("min-count")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_minimum_count)
+                        .default_value("10")
+                        .help("Skip bigrams seen fewer than this many times, whose PMI is mostly noise.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("number_of_pairs")
+                        .long("number-of-pairs")
+                        .short("k")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_pairs)
+                        .default_value("100")
+                        .help("How many bigrams to print, highest PMI first.")
+                        .value_name("POSITIVE INTEGER"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("transition-matrix")
+                .about("Write the transition probabilities P(w2 | w1) between the top K words")
+                .arg(
+                    Arg::with_name("input_file")
+                        .long("input-file")
+                        .short("f")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("GZIP-compressed frequencies file as produced by the 'create-frequencies' sub-command")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("output_file")
+                        .long("output-file")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Name of output file to put the matrix. Will not be compressed.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("top_k")
+                        .long("top-k")
+                        .short("k")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_number_of_words)
+                        .default_value("1000")
+                        .help("Number of words in the matrix, starting with most frequent. Rows sum to at most 1, the rest being the probability of moving to any other word.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("matrix_format")
+                        .long("format")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["sparse", "dense"])
+                        .default_value("sparse")
+                        .help("'sparse' writes w1<TAB>w2<TAB>probability lines for the non-zero entries, 'dense' a CSV file with a header row of words and every entry."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("probabilities")
+                .about("Write every unigram with its probability, most probable first, without counts or bigrams")
+                .arg(
+                    Arg::with_name("input_file")
+                        .long("input-file")
+                        .short("f")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("GZIP-compressed frequencies file as produced by the 'create-frequencies' sub-command")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("output_file")
+                        .long("output-file")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Name of output file to put word<TAB>probability lines, the count divided by 'total unigrams'. GZIP compressed if it ends in .gz.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Overwrite the output file if it already exists."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Load a frequencies file once and answer word lookups, one word per line")
+                .arg(
+                    Arg::with_name("input_file")
+                        .long("input-file")
+                        .short("f")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("GZIP-compressed frequencies file as produced by the 'create-frequencies' sub-command")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .required(false)
+                        .takes_value(true)
+                        .help("Serve over TCP on this address, e.g. 127.0.0.1:7878, instead of stdin/stdout.")
+                        .value_name("ADDRESS"),
+                )
+                .arg(
+                    Arg::with_name("bigrams")
+                        .long("bigrams")
+                        .required(false)
+                        .takes_value(false)
+                        .help("Also load the bigrams and answer lines of two words, w1 w2, with P(w2 | w1). Bigrams that weren't seen, or were filtered out, back off to 0.4 times the unigram probability of w2. Answers are w1<TAB>w2<TAB>count<TAB>probability<TAB>source, where source is 'bigram' or 'backoff'."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("merge")
+                .about("Merge frequencies files by summing their counts, then filtering again")
+                .arg(
+                    Arg::with_name("input_file")
+                        .long("input-file")
+                        .short("f")
+                        .required(true)
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .validator(input_path_is_file)
+                        .help("GZIP-compressed frequencies file as produced by the 'create-frequencies' sub-command. Pass once per file to merge, ideally files created with '--article-counts'.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("output_file")
+                        .long("output-file")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Path of the merged frequencies file. Will be GZIP compressed.")
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("from-counts")
+                .about("Create a frequencies file with only unigrams from a TSV file of word counts")
+                .arg(
+                    Arg::with_name("input_file")
+                        .long("input-file")
+                        .short("f")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("TSV file, optionally GZIP compressed, with a word and its count on each line separated by a tab, e.g. counts made by another tool. Counts must be non-negative integers.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("output_file")
+                        .long("output-file")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Path of the frequencies file. Will be GZIP compressed.")
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sample")
+                .about("Write a random sample of article texts from a cirrussearch JSON GZ file")
+                .arg(
+                    Arg::with_name("input_path")
+                        .long("input-path")
+                        .short("p")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(input_path_is_file)
+                        .help("Path to cirrussearch JSON GZ (or uncompressed JSON lines) file, download from https://dumps.wikimedia.org/other/cirrussearch/")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("output_file")
+                        .long("output-file")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Output file for the sampled article texts, one per line. Will be overwritten if exists.")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::with_name("lines")
+                        .long("lines")
+                        .short("n")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(validate_lines)
+                        .help("Number of article texts to sample. If the input has fewer, all are written.")
+                        .value_name("POSITIVE INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_seed)
+                        .default_value("42")
+                        .help("Seed for the random number generator, the same seed gives the same sample.")
+                        .value_name("INTEGER"),
+                )
+                .arg(
+                    Arg::with_name("input_compression")
+                        .long("input-compression")
+                        .required(false)
+                        .takes_value(true)
+                        .possible_values(&["auto", "gzip", "bzip2", "none"])
+                        .default_value("auto")
+                        .help("Compression of the input file. 'auto' picks gzip for .gz, bzip2 for .bz2, none for .json/.jsonl/.ndjson, and otherwise checks the file contents."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print a shell completion script for bash, zsh, fish, powershell or elvish")
+                .arg(
+                    Arg::with_name("shell")
+                        .required(true)
+                        .possible_values(&Shell::variants())
+                        .help("Shell to generate the completion script for."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("make-fixtures")
+                .about("Write a small random cirrussearch JSON GZ dump and its frequencies file, for tests")
+                .setting(developer_setting)
+                .arg(
+                    Arg::with_name("output_dir")
+                        .long("output-dir")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("Output directory for the fixtures, created if missing. Existing fixtures in it are overwritten.")
+                        .value_name("DIR"),
+                )
+                .arg(
+                    Arg::with_name("articles")
+                        .long("articles")
+                        .short("n")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_articles)
+                        .default_value("10")
+                        .help("Number of articles to generate.")
+                        .value_name("POSITIVE INTEGER"),
                 )
                 .arg(
-                    Arg::with_name("number_of_words")
-                        .long("number-of-words")
-                        .short("k")
+                    Arg::with_name("words")
+                        .long("words")
+                        .short("w")
                         .required(false)
                         .takes_value(true)
-                        .validator(validate_number_of_words)
-                        .default_value("10000")
-                        .help("Number of words to return, starting with most frequent.")
+                        .validator(validate_words)
+                        .default_value("20")
+                        .help("Number of words in each article.")
                         .value_name("POSITIVE INTEGER"),
                 )
                 .arg(
-                    Arg::with_name("minimum_word_length")
-                        .long("minimum-word-length")
-                        .short("m")
+                    Arg::with_name("vocabulary_size")
+                        .long("vocabulary-size")
                         .required(false)
                         .takes_value(true)
-                        .validator(validate_minimum_word_length)
-                        .default_value("3")
-                        .help("Minimum (inclusive) length of word to consider.")
+                        .validator(validate_words)
+                        .conflicts_with("vocabulary")
+                        .help("Number of distinct words to generate, \"a\" to \"z\" then \"aa\", \"ab\" and so on. [default: 26]")
                         .value_name("POSITIVE INTEGER"),
                 )
-        );
-    let matches = app.get_matches();
+                .arg(
+                    Arg::with_name("vocabulary")
+                        .long("vocabulary")
+                        .required(false)
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .help("Comma-separated words to use instead of generated ones, most frequent first, e.g. real words that are in the dictionary.")
+                        .value_name("WORDS"),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .required(false)
+                        .takes_value(true)
+                        .validator(validate_seed)
+                        .default_value("42")
+                        .help("Seed for the random number generator, the same seed gives the same fixtures.")
+                        .value_name("INTEGER"),
+                ),
+        )
+}
+
+/// `--stats-file`, taken by the pipeline stages that append a record to the run log, `split`,
+/// `create-frequencies` and `top-k-words`.
+fn stats_file_arg() -> Arg<'static, 'static> {
+    Arg::with_name("stats_file")
+        .long("stats-file")
+        .takes_value(true)
+        .help("Append a JSON line describing this run, e.g. its input, output, counts and duration, to this file. Pass the same file to split, create-frequencies and top-k-words to keep a record of how a word list was made.")
+        .value_name("FILE")
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let matches = app(false).get_matches();
+    init_logger(matches.occurrences_of("verbose"));
+    if matches.is_present("help_hidden") {
+        app(true).print_help()?;
+        println!();
+        return Ok(());
+    }
 
     match matches.subcommand() {
         ("split", Some(split_matches)) => {
@@ -132,10 +1198,69 @@ fn main() -> Result<(), Box<dyn Error>> {
             let output_dir = Path::new(split_matches.value_of("output_dir").unwrap());
             let pieces = split_matches
                 .value_of("pieces")
+                .map_or(12, |pieces| pieces.parse::<u32>().unwrap());
+            let chunk_lines = split_matches
+                .value_of("chunk_lines")
+                .map(|lines| lines.parse::<u64>().unwrap());
+            let assignment = match chunk_lines {
+                Some(lines) => split::PieceAssignment::Chunks(lines),
+                None if split_matches.is_present("preserve_order") => {
+                    split::PieceAssignment::Contiguous
+                }
+                None => split::PieceAssignment::Random,
+            };
+            let resume = split_matches.is_present("resume");
+            let input_compression = split_matches
+                .value_of("input_compression")
                 .unwrap()
-                .parse::<u32>()
-                .unwrap();
-            split::handle_split(input_path, output_dir, pieces)
+                .parse::<util::InputCompression>()?;
+            let start_line = split_matches
+                .value_of("start_line")
+                .map_or(1, |line| line.parse::<u64>().unwrap());
+            let end_line = split_matches
+                .value_of("end_line")
+                .map(|line| line.parse::<u64>().unwrap());
+            let excluded_ids = match split_matches.value_of("exclude_ids_file") {
+                Some(path) => Some(split::load_excluded_ids(Path::new(path))?),
+                None => None,
+            };
+            let text_pointer = split_matches.value_of("json_pointer").unwrap();
+            let timer = run_log::StageTimer::start();
+            let (articles_written, pieces) = split::handle_split(
+                input_path,
+                output_dir,
+                pieces,
+                assignment,
+                resume,
+                input_compression,
+                start_line,
+                end_line,
+                split_matches.is_present("single_file"),
+                excluded_ids.as_ref(),
+                text_pointer,
+                split_matches.is_present("skip_redirects"),
+            )?;
+            if let Some(stats_file) = split_matches.value_of("stats_file") {
+                run_log::append_record(
+                    Path::new(stats_file),
+                    "split",
+                    &timer,
+                    json!({
+                        "input": input_path,
+                        "output": output_dir,
+                        "pieces": pieces,
+                        "chunk_lines": chunk_lines,
+                        "preserve_order": assignment == split::PieceAssignment::Contiguous,
+                        "seed": split::SPLIT_SEED,
+                        "json_pointer": text_pointer,
+                        "start_line": start_line,
+                        "end_line": end_line,
+                        "resumed": resume,
+                        "articles_written": articles_written,
+                    }),
+                )?;
+            }
+            Ok(())
         }
         ("create-frequencies", Some(create_frequencies_matches)) => {
             let input_dir = Path::new(create_frequencies_matches.value_of("input_dir").unwrap());
@@ -147,7 +1272,141 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .value_of("language")
                 .unwrap()
                 .to_string();
-            create_frequencies::handle_create_frequencies(input_dir, &output_file, &language_code)
+            let mut trim_chars = util::TrimChars::for_languages(&language_code);
+            if let Some(chars) = create_frequencies_matches.value_of("trim_chars") {
+                trim_chars.only = Some(chars.chars().collect());
+            }
+            if let Some(chars) = create_frequencies_matches.value_of("keep_chars") {
+                trim_chars.keep.extend(chars.chars());
+            }
+            let threads = create_frequencies_matches
+                .value_of("threads")
+                .map_or_else(create_frequencies::thread_count, |threads| {
+                    threads.parse::<u32>().unwrap()
+                });
+            let options = create_frequencies::CreateFrequenciesOptions {
+                external_sort: create_frequencies_matches.is_present("external_sort"),
+                drop_oov: create_frequencies_matches.is_present("drop_oov"),
+                exclude_oov_from_total: create_frequencies_matches
+                    .is_present("exclude_oov_from_total"),
+                max_tokens_per_line: create_frequencies_matches
+                    .value_of("max_tokens_per_line")
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap(),
+                fold_dictionary_case: create_frequencies_matches.is_present("fold_dictionary_case"),
+                split_hyphens: create_frequencies_matches.is_present("split_hyphens"),
+                nfkc_normalize: !create_frequencies_matches.is_present("assume_normalized"),
+                max_memory_per_thread: create_frequencies_matches.value_of("max_memory").map(
+                    |megabytes| megabytes.parse::<u64>().unwrap() * 1024 * 1024 / threads as u64,
+                ),
+                trim_chars,
+                article_counts: create_frequencies_matches.is_present("article_counts"),
+                two_pass: create_frequencies_matches.is_present("two_pass"),
+                sample_oov: create_frequencies_matches
+                    .value_of("sample_oov")
+                    .map(|lines| lines.parse::<u64>().unwrap()),
+                keep_going: create_frequencies_matches.is_present("keep_going"),
+                pretty: create_frequencies_matches.is_present("pretty"),
+                input_json: if create_frequencies_matches.is_present("input_json") {
+                    Some(match create_frequencies_matches.values_of("json_fields") {
+                        Some(fields) => fields.map(String::from).collect(),
+                        None => vec![String::from(split::DEFAULT_TEXT_POINTER)],
+                    })
+                } else {
+                    None
+                },
+                report_top_oov: create_frequencies_matches
+                    .value_of("report_top_oov")
+                    .map(|top_oov| top_oov.parse::<usize>().unwrap()),
+                length_normalize: create_frequencies_matches.is_present("length_normalize"),
+                window: create_frequencies_matches
+                    .value_of("window")
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap(),
+                distance_weighting: create_frequencies_matches.is_present("distance_weighting"),
+                dedup_bigrams_per_article: create_frequencies_matches
+                    .is_present("dedup_bigrams_per_article"),
+                track_case: create_frequencies_matches.is_present("track_case"),
+                sort_stable: create_frequencies_matches.is_present("sort_stable"),
+                strip_accents: create_frequencies_matches.is_present("strip_accents"),
+                vocabulary_growth: create_frequencies_matches.is_present("vocabulary_growth"),
+                force: create_frequencies_matches.is_present("force"),
+                largest_first: create_frequencies_matches.is_present("largest_first"),
+                threads,
+                deterministic: create_frequencies_matches.is_present("deterministic"),
+                cache_counts: create_frequencies_matches
+                    .value_of("cache_counts")
+                    .map(PathBuf::from),
+                max_vocab: create_frequencies_matches
+                    .value_of("max_vocab")
+                    .map(|max_vocab| max_vocab.parse::<usize>().unwrap()),
+                boundary_tokens: if create_frequencies_matches.is_present("article_boundaries") {
+                    let boundary_tokens = create_frequencies_matches
+                        .value_of("boundary_tokens")
+                        .unwrap_or("<s>,</s>");
+                    let (start, end) = boundary_tokens.split_once(',').unwrap();
+                    Some((start.to_string(), end.to_string()))
+                } else {
+                    None
+                },
+                number_class: create_frequencies_matches
+                    .value_of("number_class")
+                    .map(String::from),
+                script: create_frequencies_matches
+                    .value_of("script")
+                    .map(|script| script.parse::<util::Script>())
+                    .transpose()?,
+                report_memory: create_frequencies_matches.is_present("report_memory"),
+                unit: create_frequencies_matches
+                    .value_of("unit")
+                    .unwrap()
+                    .parse::<create_frequencies::Unit>()?,
+                input_encoding: create_frequencies_matches
+                    .value_of("input_encoding")
+                    .map(|label| Encoding::for_label(label.as_bytes()).unwrap())
+                    .filter(|encoding| *encoding != encoding_rs::UTF_8),
+                output_format: create_frequencies_matches
+                    .value_of("format")
+                    .unwrap()
+                    .parse::<create_frequencies::OutputFormat>()?,
+            };
+            let timer = run_log::StageTimer::start();
+            create_frequencies::handle_create_frequencies(
+                input_dir,
+                &output_file,
+                &language_code,
+                &options,
+            )?;
+            // A dry run with --sample-oov writes no frequencies file to describe.
+            if let (Some(stats_file), None) = (
+                create_frequencies_matches.value_of("stats_file"),
+                options.sample_oov,
+            ) {
+                let output_path =
+                    create_frequencies::output_path(input_dir, &output_file, options.output_format);
+                // Output written to stdout can't be read back to summarize it.
+                let mut fields = if output_file == create_frequencies::STDOUT_OUTPUT_FILE {
+                    json!({})
+                } else {
+                    run_log::frequencies_summary(&output_path)?
+                };
+                fields["input"] = json!(input_dir);
+                fields["output"] = json!(output_path);
+                fields["language"] = json!(language_code);
+                fields["article_counts"] = json!(options.article_counts);
+                fields["drop_oov"] = json!(options.drop_oov);
+                fields["fold_dictionary_case"] = json!(options.fold_dictionary_case);
+                fields["max_vocab"] = json!(options.max_vocab);
+                run_log::append_record(
+                    Path::new(stats_file),
+                    "create-frequencies",
+                    &timer,
+                    fields,
+                )?;
+            }
+            Ok(())
         }
         ("top-k-words", Some(top_k_words_matches)) => {
             let input_file = Path::new(top_k_words_matches.value_of("input_file").unwrap());
@@ -155,28 +1414,322 @@ fn main() -> Result<(), Box<dyn Error>> {
             let minimum_word_length = top_k_words_matches
                 .value_of("minimum_word_length")
                 .unwrap()
-                .parse::<u32>()
+                .parse::<usize>()
                 .unwrap();
+            let min_lengths: Option<Vec<usize>> = top_k_words_matches
+                .values_of("min_lengths")
+                .map(|lengths| lengths.map(|length| length.parse().unwrap()).collect());
+            let outputs: Vec<(PathBuf, usize)> = match &min_lengths {
+                Some(lengths) => lengths
+                    .iter()
+                    .map(|length| {
+                        (
+                            topkwords::min_length_output_file(output_file, *length),
+                            *length,
+                        )
+                    })
+                    .collect(),
+                None => vec![(output_file.to_path_buf(), minimum_word_length)],
+            };
+            for (output_file, _length) in outputs.iter() {
+                util::check_not_overwriting(output_file, top_k_words_matches.is_present("force"))?;
+            }
             let number_of_words = top_k_words_matches
                 .value_of("number_of_words")
                 .unwrap()
                 .parse::<u32>()
                 .unwrap();
+            let sort_alphabetically = top_k_words_matches.value_of("sort").unwrap() == "alpha";
+            let output_column = if top_k_words_matches.is_present("per_million") {
+                topkwords::OutputColumn::PerMillion
+            } else if top_k_words_matches.is_present("idf") {
+                topkwords::OutputColumn::Idf
+            } else if top_k_words_matches.is_present("burstiness") {
+                topkwords::OutputColumn::Burstiness
+            } else {
+                topkwords::OutputColumn::None
+            };
+            let priors = match top_k_words_matches.value_of("priors") {
+                Some(path) => Some(topkwords::Priors {
+                    weights: util::load_dictionary_priors(Path::new(path))?,
+                    blend: top_k_words_matches
+                        .value_of("prior_blend")
+                        .map_or(0.5, |blend| blend.parse::<f64>().unwrap()),
+                }),
+                None => None,
+            };
+            if sort_alphabetically && top_k_words_matches.is_present("stable_against") {
+                let err: Box<dyn Error> =
+                    String::from("--stable-against cannot be used with --sort alpha").into();
+                return Err(err);
+            }
+            let with_rank = if top_k_words_matches.is_present("with_rank") {
+                Some(topkwords::RankFormat {
+                    base: top_k_words_matches
+                        .value_of("id_base")
+                        .map_or(1, |base| base.parse::<u64>().unwrap()),
+                    width: top_k_words_matches
+                        .value_of("id_width")
+                        .map_or(0, |width| width.parse::<usize>().unwrap()),
+                })
+            } else {
+                None
+            };
+            let stable_against = match top_k_words_matches.value_of("stable_against") {
+                Some(path) => Some(topkwords::load_word_list(Path::new(path))?),
+                None => None,
+            };
+            let timer = run_log::StageTimer::start();
             topkwords::handle_top_k_words(
                 input_file,
-                output_file,
-                minimum_word_length as usize,
+                &outputs,
                 number_of_words as usize,
+                top_k_words_matches.is_present("include_ties"),
+                sort_alphabetically,
+                top_k_words_matches.value_of("language").unwrap_or(""),
+                output_column,
+                with_rank,
+                top_k_words_matches.is_present("lowercase_output"),
+                priors.as_ref(),
+                stable_against.as_deref(),
+            )?;
+            if let Some(stats_file) = top_k_words_matches.value_of("stats_file") {
+                run_log::append_record(
+                    Path::new(stats_file),
+                    "top-k-words",
+                    &timer,
+                    json!({
+                        "input": input_file,
+                        "output": output_file,
+                        "number_of_words": number_of_words,
+                        "minimum_word_length": minimum_word_length,
+                        "min_lengths": min_lengths,
+                    }),
+                )?;
+            }
+            Ok(())
+        }
+        ("dict-info", Some(dict_info_matches)) => {
+            let language_code = dict_info_matches.value_of("language").unwrap();
+            let fold_case = dict_info_matches.is_present("fold_case");
+            dict_info::handle_dict_info(language_code, fold_case)
+        }
+        ("dict-diff", Some(dict_diff_matches)) => {
+            let input_file = Path::new(dict_diff_matches.value_of("input_file").unwrap());
+            let language_code = dict_diff_matches.value_of("language").unwrap();
+            let top_missing = dict_diff_matches
+                .value_of("top_missing")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+            dict_info::handle_dict_diff(
+                input_file,
+                language_code,
+                dict_diff_matches.is_present("fold_case"),
+                top_missing,
+            )
+        }
+        ("generate", Some(generate_matches)) => {
+            let input_file = Path::new(generate_matches.value_of("input_file").unwrap());
+            let number_of_words = generate_matches
+                .value_of("words")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+            let use_bigrams = generate_matches.value_of("mode").unwrap() == "bigram";
+            let seed = generate_matches
+                .value_of("seed")
+                .unwrap()
+                .parse::<u64>()
+                .unwrap();
+            generate::handle_generate(input_file, number_of_words, use_bigrams, seed)
+        }
+        ("stats", Some(stats_matches)) => {
+            let input_file = Path::new(stats_matches.value_of("input_file").unwrap());
+            let confidence_z = stats_matches
+                .value_of("confidence_interval")
+                .map(|percent| match percent {
+                    "90" => 1.644_854,
+                    "95" => 1.959_964,
+                    _ => 2.575_829,
+                });
+            let entropy_words = stats_matches
+                .value_of("following_entropy")
+                .map(|words| words.parse::<usize>().unwrap());
+            let successor_words = stats_matches
+                .value_of("distinct_successors")
+                .map(|words| words.parse::<usize>().unwrap());
+            let per_million = stats_matches.is_present("per_million");
+            stats::handle_stats(
+                input_file,
+                confidence_z,
+                entropy_words,
+                successor_words,
+                per_million,
+            )
+        }
+        ("pmi", Some(pmi_matches)) => {
+            let input_file = Path::new(pmi_matches.value_of("input_file").unwrap());
+            let minimum_count = pmi_matches
+                .value_of("min_count")
+                .unwrap()
+                .parse::<u64>()
+                .unwrap();
+            let number_of_pairs = pmi_matches
+                .value_of("number_of_pairs")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+            pmi::handle_pmi(input_file, minimum_count, number_of_pairs)
+        }
+        ("transition-matrix", Some(transition_matrix_matches)) => {
+            let input_file = Path::new(transition_matrix_matches.value_of("input_file").unwrap());
+            let output_file = Path::new(transition_matrix_matches.value_of("output_file").unwrap());
+            let top_k = transition_matrix_matches
+                .value_of("top_k")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+            let format = transition_matrix_matches
+                .value_of("matrix_format")
+                .unwrap()
+                .parse::<transition_matrix::MatrixFormat>()?;
+            transition_matrix::handle_transition_matrix(input_file, output_file, top_k, format)
+        }
+        ("probabilities", Some(probabilities_matches)) => {
+            let input_file = Path::new(probabilities_matches.value_of("input_file").unwrap());
+            let output_file = Path::new(probabilities_matches.value_of("output_file").unwrap());
+            util::check_not_overwriting(output_file, probabilities_matches.is_present("force"))?;
+            probabilities::handle_probabilities(input_file, output_file)
+        }
+        ("completions", Some(completions_matches)) => {
+            let shell = completions_matches
+                .value_of("shell")
+                .unwrap()
+                .parse::<Shell>()?;
+            app(false).gen_completions_to("word-frequencies", shell, &mut io::stdout());
+            Ok(())
+        }
+        ("serve", Some(serve_matches)) => {
+            let input_file = Path::new(serve_matches.value_of("input_file").unwrap());
+            serve::handle_serve(
+                input_file,
+                serve_matches.value_of("listen"),
+                serve_matches.is_present("bigrams"),
+            )
+        }
+        ("merge", Some(merge_matches)) => {
+            let input_files: Vec<&Path> = merge_matches
+                .values_of("input_file")
+                .unwrap()
+                .map(Path::new)
+                .collect();
+            let output_file = Path::new(merge_matches.value_of("output_file").unwrap());
+            merge::handle_merge(&input_files, output_file)
+        }
+        ("from-counts", Some(from_counts_matches)) => {
+            let input_file = Path::new(from_counts_matches.value_of("input_file").unwrap());
+            let output_file = Path::new(from_counts_matches.value_of("output_file").unwrap());
+            from_counts::handle_from_counts(input_file, output_file)
+        }
+        ("sample", Some(sample_matches)) => {
+            let input_path = Path::new(sample_matches.value_of("input_path").unwrap());
+            let output_file = Path::new(sample_matches.value_of("output_file").unwrap());
+            let number_of_lines = sample_matches
+                .value_of("lines")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+            let seed = sample_matches
+                .value_of("seed")
+                .unwrap()
+                .parse::<u64>()
+                .unwrap();
+            let input_compression = sample_matches
+                .value_of("input_compression")
+                .unwrap()
+                .parse::<util::InputCompression>()?;
+            sample::handle_sample(
+                input_path,
+                output_file,
+                number_of_lines,
+                seed,
+                input_compression,
+            )
+        }
+        ("make-fixtures", Some(make_fixtures_matches)) => {
+            let output_dir = Path::new(make_fixtures_matches.value_of("output_dir").unwrap());
+            let articles = make_fixtures_matches
+                .value_of("articles")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+            let words_per_article = make_fixtures_matches
+                .value_of("words")
+                .unwrap()
+                .parse::<usize>()
+                .unwrap();
+            let vocabulary = match make_fixtures_matches.values_of("vocabulary") {
+                Some(words) => words.map(String::from).collect(),
+                None => fixtures::generated_vocabulary(
+                    make_fixtures_matches
+                        .value_of("vocabulary_size")
+                        .unwrap_or("26")
+                        .parse::<usize>()
+                        .unwrap(),
+                ),
+            };
+            let seed = make_fixtures_matches
+                .value_of("seed")
+                .unwrap()
+                .parse::<u64>()
+                .unwrap();
+            fixtures::handle_make_fixtures(
+                output_dir,
+                articles,
+                words_per_article,
+                &vocabulary,
+                seed,
             )
         }
         ("", None) => {
             let err: Box<dyn Error> = String::from("Need to specify a sub-command.").into();
             Err(err)
         }
-        _ => unreachable!(),
+        (subcommand, _) => {
+            let err: Box<dyn Error> = format!("Unknown sub-command {}.", subcommand).into();
+            Err(err)
+        }
     }
 }
 
+/// Log to stderr at info level, or debug or trace with `-v` or `-vv`. Info lines are just the
+/// message, as progress used to be printed, other levels are prefixed with the level.
+fn init_logger(verbosity: u64) {
+    let level = match verbosity {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    builder
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            Level::Warn => writeln!(buf, "warning: {}", record.args()),
+            level => writeln!(
+                buf,
+                "{}: {}",
+                level.to_string().to_lowercase(),
+                record.args()
+            ),
+        })
+        .init();
+}
+
 fn validate_pieces(input: String) -> Result<(), String> {
     match input.parse::<u32>() {
         Ok(value) => {
@@ -192,6 +1745,29 @@ fn validate_pieces(input: String) -> Result<(), String> {
     }
 }
 
+fn validate_json_pointer(input: String) -> Result<(), String> {
+    if input.is_empty() || input.starts_with('/') {
+        Ok(())
+    } else {
+        Err(String::from(
+            "JSON pointer must be empty or start with '/'.",
+        ))
+    }
+}
+
+fn validate_line_number(input: String) -> Result<(), String> {
+    match input.parse::<u64>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Line numbers start at 1."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Line number is not a valid integer.")),
+    }
+}
+
 fn validate_number_of_words(input: String) -> Result<(), String> {
     match input.parse::<u32>() {
         Ok(value) => {
@@ -222,6 +1798,239 @@ fn validate_minimum_word_length(input: String) -> Result<(), String> {
     }
 }
 
+fn validate_max_tokens_per_line(input: String) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Max tokens per line cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Max tokens per line is not a valid integer.")),
+    }
+}
+
+fn validate_words(input: String) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Words cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Words is not a valid integer.")),
+    }
+}
+
+fn validate_articles(input: String) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Articles cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Articles is not a valid integer.")),
+    }
+}
+
+fn validate_window(input: String) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Window cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Window is not a valid integer.")),
+    }
+}
+
+fn validate_threads(input: String) -> Result<(), String> {
+    match input.parse::<u32>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Threads cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Threads is not a valid integer.")),
+    }
+}
+
+fn validate_max_vocab(input: String) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Max vocab cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Max vocab is not a valid integer.")),
+    }
+}
+
+fn validate_report_top_oov(input: String) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Number of OOV words cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Number of OOV words is not a valid integer.")),
+    }
+}
+
+fn validate_prior_blend(input: String) -> Result<(), String> {
+    match input.parse::<f64>() {
+        Ok(value) => {
+            if (0.0..=1.0).contains(&value) {
+                Ok(())
+            } else {
+                Err(String::from("Prior blend must be between 0 and 1."))
+            }
+        }
+        Err(_) => Err(String::from("Prior blend is not a valid number.")),
+    }
+}
+
+fn validate_minimum_count(input: String) -> Result<(), String> {
+    match input.parse::<u64>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Minimum count cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Minimum count is not a valid integer.")),
+    }
+}
+
+fn validate_pairs(input: String) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Number of pairs cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Number of pairs is not a valid integer.")),
+    }
+}
+
+fn validate_number_class(input: String) -> Result<(), String> {
+    if input.is_empty() || input.contains(char::is_whitespace) {
+        Err(String::from(
+            "Number class must be non-empty and contain no whitespace.",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_boundary_tokens(input: String) -> Result<(), String> {
+    match input.split_once(',') {
+        Some((start, end))
+            if !start.is_empty()
+                && !end.is_empty()
+                && start != end
+                && !format!("{}{}", start, end)
+                    .contains(|c: char| c.is_whitespace() || c == ',') =>
+        {
+            Ok(())
+        }
+        _ => Err(String::from(
+            "Boundary tokens must be two different comma-separated tokens without whitespace.",
+        )),
+    }
+}
+
+fn validate_trim_chars(input: String) -> Result<(), String> {
+    if input.is_empty() {
+        Err(String::from("Characters cannot be empty."))
+    } else if input.contains(char::is_whitespace) {
+        Err(String::from(
+            "Characters cannot include whitespace, it's always trimmed.",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_input_encoding(input: String) -> Result<(), String> {
+    match Encoding::for_label(input.as_bytes()) {
+        Some(encoding) if encoding.is_ascii_compatible() => Ok(()),
+        Some(encoding) => Err(format!(
+            "Encoding {} is not ASCII-compatible, so lines can't be split on line breaks.",
+            encoding.name()
+        )),
+        None => Err(format!("Unknown encoding {}.", input)),
+    }
+}
+
+fn validate_lines(input: String) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Lines cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Lines is not a valid integer.")),
+    }
+}
+
+fn validate_id_width(input: String) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("ID width cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("ID width is not a valid integer.")),
+    }
+}
+
+fn validate_id_base(input: String) -> Result<(), String> {
+    match input.parse::<u64>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(String::from("ID base is not a valid non-negative integer.")),
+    }
+}
+
+fn validate_seed(input: String) -> Result<(), String> {
+    match input.parse::<u64>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(String::from("Seed is not a valid non-negative integer.")),
+    }
+}
+
+fn validate_max_memory(input: String) -> Result<(), String> {
+    match input.parse::<u64>() {
+        Ok(value) => {
+            if value == 0 {
+                Err(String::from("Max memory cannot be 0."))
+            } else {
+                Ok(())
+            }
+        }
+        Err(_) => Err(String::from("Max memory is not a valid integer.")),
+    }
+}
+
 fn input_path_is_file(input: String) -> Result<(), String> {
     if Path::new(&input).is_file() {
         Ok(())
@@ -242,6 +2051,12 @@ fn validate_input_dir(input: String) -> Result<(), String> {
     }
 }
 
+fn validate_language_codes(input: String) -> Result<(), String> {
+    input
+        .split(',')
+        .try_for_each(|language_code| validate_language_code(language_code.to_string()))
+}
+
 fn validate_language_code(input: String) -> Result<(), String> {
     match input.as_str() {
         "en" | "pl" => Ok(()),
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use flate2::{Compression, GzBuilder};
+use log::{info, warn};
+
+use crate::create_frequencies::passes_article_threshold;
+use crate::frequencies::{
+    FrequenciesEntry, FrequenciesHeader, FrequenciesReader, FrequenciesWriter,
+};
+use crate::util::gzip_member_filename;
+
+/// Merge frequencies files, e.g. built from different dumps, into one GZIP-compressed file by
+/// summing their counts.
+///
+/// The article threshold is applied again after summing, and a bigram is only written if both of
+/// its unigrams are, so the merged file is consistent however its inputs were filtered. This
+/// needs article counts: unigrams from files written without `--article-counts` are treated as
+/// having none and always pass. Even with them the merge is only exact for unigrams that were
+/// written to every input, since counts that an input filtered out are lost.
+pub fn handle_merge(input_files: &[&Path], output_file: &Path) -> Result<(), Box<dyn Error>> {
+    let mut total_unigrams = 0;
+    let mut total_articles = Some(0);
+    let mut unigram_counts: BTreeMap<String, u64> = BTreeMap::new();
+    let mut unigram_article_counts: HashMap<String, u64> = HashMap::new();
+    let mut has_article_counts = true;
+    let mut bigram_counts: BTreeMap<(String, String), u64> = BTreeMap::new();
+    for input_file in input_files {
+        info!("reading {:?}...", input_file);
+        let reader = FrequenciesReader::open(input_file)?;
+        total_unigrams += reader.header().total_unigrams;
+        total_articles = match (total_articles, reader.header().total_articles) {
+            (Some(total), Some(file_total)) => Some(total + file_total),
+            _ => None,
+        };
+        for entry in reader {
+            match entry? {
+                FrequenciesEntry::Unigram(token, count, article_count) => {
+                    match article_count {
+                        Some(article_count) => {
+                            *unigram_article_counts.entry(token.clone()).or_insert(0) +=
+                                article_count
+                        }
+                        None => has_article_counts = false,
+                    }
+                    *unigram_counts.entry(token).or_insert(0) += count;
+                }
+                FrequenciesEntry::Bigram(token1, token2, count) => {
+                    *bigram_counts.entry((token1, token2)).or_insert(0) += count;
+                }
+            }
+        }
+    }
+    if !has_article_counts {
+        warn!(
+            "not every input has article counts, unigrams without them always pass the \
+             article threshold"
+        );
+    }
+
+    let surviving_unigrams: HashSet<&str> = unigram_counts
+        .keys()
+        .filter(|token| passes_article_threshold(unigram_article_counts.get(*token).copied()))
+        .map(String::as_str)
+        .collect();
+
+    info!("writing merged frequencies to {:?}...", output_file);
+    let output = BufWriter::new(
+        GzBuilder::new()
+            .filename(gzip_member_filename(output_file))
+            .write(File::create(output_file)?, Compression::best()),
+    );
+    let header = FrequenciesHeader {
+        total_unigrams,
+        total_articles,
+        unigrams: unigram_counts.len() as u64,
+        bigrams: bigram_counts.len() as u64,
+    };
+    let mut output = FrequenciesWriter::new(output, &header)?;
+    output.begin_unigrams()?;
+    for (token, count) in unigram_counts.iter() {
+        if !surviving_unigrams.contains(token.as_str()) {
+            continue;
+        }
+        if has_article_counts {
+            output.write_unigram_with_article_count(
+                token,
+                *count,
+                unigram_article_counts[token],
+            )?;
+        } else {
+            output.write_unigram(token, *count)?;
+        }
+    }
+    output.begin_bigrams()?;
+    for ((token1, token2), count) in bigram_counts.iter() {
+        if surviving_unigrams.contains(token1.as_str())
+            && surviving_unigrams.contains(token2.as_str())
+        {
+            output.write_bigram(token1, token2, *count)?;
+        }
+    }
+    output.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a frequencies file of `(token, count, article count)` unigrams and bigrams.
+    fn write_input(
+        path: &Path,
+        unigrams: &[(&str, u64, u64)],
+        bigrams: &[(&str, &str, u64)],
+    ) -> Result<(), Box<dyn Error>> {
+        let header = FrequenciesHeader {
+            total_unigrams: unigrams
+                .iter()
+                .map(|(_token, count, _articles)| count)
+                .sum(),
+            total_articles: Some(40),
+            unigrams: unigrams.len() as u64,
+            bigrams: bigrams.len() as u64,
+        };
+        let mut output = FrequenciesWriter::new(File::create(path)?, &header)?;
+        output.begin_unigrams()?;
+        for (token, count, article_count) in unigrams {
+            output.write_unigram_with_article_count(token, *count, *article_count)?;
+        }
+        output.begin_bigrams()?;
+        for (token1, token2, count) in bigrams {
+            output.write_bigram(token1, token2, *count)?;
+        }
+        output.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn article_counts_are_summed_before_the_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let input1 = dir.path().join("1.txt");
+        let input2 = dir.path().join("2.txt");
+        // "the" and "cat" only pass the threshold once their article counts are summed, "dog"
+        // doesn't even then.
+        write_input(
+            &input1,
+            &[("cat", 10, 25), ("dog", 10, 5), ("the", 100, 30)],
+            &[("the", "cat", 5), ("the", "dog", 3)],
+        )
+        .unwrap();
+        write_input(
+            &input2,
+            &[("cat", 10, 20), ("dog", 5, 5), ("the", 100, 30)],
+            &[("cat", "the", 2), ("the", "cat", 4), ("the", "dog", 1)],
+        )
+        .unwrap();
+        let output_file = dir.path().join("merged.txt.gz");
+        handle_merge(&[input1.as_path(), input2.as_path()], &output_file).unwrap();
+
+        let reader = FrequenciesReader::open(&output_file).unwrap();
+        assert_eq!(reader.header().total_articles, Some(80));
+        let entries: Vec<FrequenciesEntry> = reader.map(Result::unwrap).collect();
+        assert_eq!(
+            entries,
+            vec![
+                FrequenciesEntry::Unigram(String::from("cat"), 20, Some(45)),
+                FrequenciesEntry::Unigram(String::from("the"), 200, Some(60)),
+                FrequenciesEntry::Bigram(String::from("cat"), String::from("the"), 2),
+                FrequenciesEntry::Bigram(String::from("the"), String::from("cat"), 9),
+            ]
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::f64::consts::LN_2;
+use std::path::Path;
+
+use crate::frequencies::{FrequenciesEntry, FrequenciesReader};
+use crate::util::{ln_probability, OUT_OF_VOCABULARY_WORD};
+
+/// Print the `number_of_pairs` bigrams with the highest pointwise mutual information as
+/// `word1\tword2\tpmi\tcount` lines, highest first. Bigrams seen fewer than `minimum_count` times
+/// are skipped, because PMI favours rare pairs: two words that each occur once, together, have
+/// the highest PMI possible.
+pub fn handle_pmi(
+    input_file: &Path,
+    minimum_count: u64,
+    number_of_pairs: usize,
+) -> Result<(), Box<dyn Error>> {
+    let reader = FrequenciesReader::open(input_file)?;
+    let total_unigrams = reader.header().total_unigrams;
+    let mut unigram_counts: HashMap<String, u64> = HashMap::new();
+    let mut associations = Vec::new();
+    for entry in reader {
+        match entry? {
+            FrequenciesEntry::Unigram(token, count, _article_count) => {
+                unigram_counts.insert(token, count);
+            }
+            FrequenciesEntry::Bigram(token1, token2, count) => {
+                if count < minimum_count
+                    || token1 == OUT_OF_VOCABULARY_WORD
+                    || token2 == OUT_OF_VOCABULARY_WORD
+                {
+                    continue;
+                }
+                // Every unigram row precedes the bigram rows, and a bigram is only written if
+                // both of its unigrams are, but check rather than trust the input.
+                let (count1, count2) =
+                    match (unigram_counts.get(&token1), unigram_counts.get(&token2)) {
+                        (Some(count1), Some(count2)) => (*count1, *count2),
+                        _ => continue,
+                    };
+                let pmi = pmi(count, count1, count2, total_unigrams);
+                associations.push((token1, token2, pmi, count));
+            }
+        }
+    }
+
+    associations.sort_by(|(word1, word2, pmi1, _), (other1, other2, pmi2, _)| {
+        pmi2.total_cmp(pmi1)
+            .then_with(|| (word1, word2).cmp(&(other1, other2)))
+    });
+    for (word1, word2, pmi, count) in associations.into_iter().take(number_of_pairs) {
+        println!("{}\t{}\t{:.4}\t{}", word1, word2, pmi, count);
+    }
+
+    Ok(())
+}
+
+/// Pointwise mutual information, in bits, of a bigram seen `count` times whose words were seen
+/// `count1` and `count2` times, out of `total` unigrams: log2(P(w1, w2) / (P(w1) P(w2))), with
+/// every probability estimated as a count divided by `total`. Positive when the words occur
+/// together more often than chance. Computed from log probabilities, see `ln_probability`, so
+/// the product of the word probabilities can't underflow for rare words in huge corpora.
+pub fn pmi(count: u64, count1: u64, count2: u64, total: u64) -> f64 {
+    (ln_probability(count, total) - ln_probability(count1, total) - ln_probability(count2, total))
+        / LN_2
+}
@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use flate2::{Compression, GzBuilder};
+
+use crate::frequencies::FrequenciesReader;
+use crate::util::gzip_member_filename;
+
+/// Write every unigram of a frequencies file with its probability, its count divided by
+/// `total unigrams`, as `word\tprobability` lines, most probable first and ties alphabetically,
+/// without counts, bigrams or any of the frequencies file's structure. OOV is written like any
+/// other word, so the probabilities sum to 1 unless `create-frequencies` dropped words. The output
+/// is GZIP compressed if its name ends in `.gz`.
+pub fn handle_probabilities(input_file: &Path, output_file: &Path) -> Result<(), Box<dyn Error>> {
+    let reader = FrequenciesReader::open(input_file)?;
+    let total_unigrams = reader.header().total_unigrams;
+    let mut unigrams = Vec::new();
+    for unigram in reader.unigrams() {
+        unigrams.push(unigram?);
+    }
+    unigrams.sort_by(|(word1, count1), (word2, count2)| {
+        count2.cmp(count1).then_with(|| word1.cmp(word2))
+    });
+
+    let file = File::create(output_file).map_err(|err| {
+        format!(
+            "Could not create output file {:?} due to {:?}",
+            output_file, err
+        )
+    })?;
+    if output_file.extension() == Some(OsStr::new("gz")) {
+        let output = GzBuilder::new()
+            .filename(gzip_member_filename(output_file))
+            .write(file, Compression::default());
+        write_probabilities(output, &unigrams, total_unigrams)?.finish()?;
+    } else {
+        write_probabilities(file, &unigrams, total_unigrams)?;
+    }
+    Ok(())
+}
+
+/// Write the `word\tprobability` lines to `output`, returning it once flushed.
+fn write_probabilities<W: Write>(
+    output: W,
+    unigrams: &[(String, u64)],
+    total_unigrams: u64,
+) -> Result<W, Box<dyn Error>> {
+    let mut output = BufWriter::new(output);
+    for (word, count) in unigrams {
+        writeln!(
+            output,
+            "{}\t{:.6e}",
+            word,
+            *count as f64 / total_unigrams as f64
+        )?;
+    }
+    Ok(output.into_inner().map_err(|err| err.into_error())?)
+}
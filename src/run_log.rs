@@ -0,0 +1,102 @@
+//! The `--stats-file` run log: one JSON object per line, appended by each of the pipeline stages
+//! `split`, `create-frequencies` and `top-k-words` that is given the same file, so that a
+//! frequencies file can be traced back to the dump, split and options it came from. Every record
+//! has `stage`, `started_at` in seconds since the Unix epoch and `duration_seconds`, plus the
+//! stage's own fields, with keys in alphabetical order, e.g.:
+//!
+//! ```text
+//! {"articles_written":1234,"duration_seconds":812.3,"input":"...","pieces":12,...,"stage":"split",...}
+//! {"bigrams":5678,"duration_seconds":3021.9,...,"oov_rate":0.08,...,"stage":"create-frequencies",...}
+//! ```
+
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+
+use crate::frequencies::{FrequenciesEntry, FrequenciesReader};
+use crate::util::OUT_OF_VOCABULARY_WORD;
+
+/// When a stage started, for the timing fields of its record.
+pub struct StageTimer {
+    started_at: SystemTime,
+    start: Instant,
+}
+
+impl StageTimer {
+    pub fn start() -> StageTimer {
+        StageTimer {
+            started_at: SystemTime::now(),
+            start: Instant::now(),
+        }
+    }
+}
+
+/// Append a record for `stage` to `stats_file`, creating it if missing. `fields` must be a JSON
+/// object, its fields follow the common ones.
+pub fn append_record(
+    stats_file: &Path,
+    stage: &str,
+    timer: &StageTimer,
+    fields: Value,
+) -> Result<(), Box<dyn Error>> {
+    let mut record = Map::new();
+    record.insert(String::from("stage"), json!(stage));
+    record.insert(
+        String::from("started_at"),
+        json!(timer
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)),
+    );
+    record.insert(
+        String::from("duration_seconds"),
+        json!(timer.start.elapsed().as_secs_f64()),
+    );
+    if let Value::Object(fields) = fields {
+        record.extend(fields);
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(stats_file)
+        .map_err(|err| {
+            format!(
+                "Could not open stats file {:?} due to {:?}",
+                stats_file, err
+            )
+        })?;
+    writeln!(file, "{}", Value::Object(record))?;
+    Ok(())
+}
+
+/// The header counts of a frequencies file and its OOV rate, the OOV unigram count divided by
+/// the total, for a record. The OOV rate is `null` if the file has no OOV row, e.g. with
+/// `--drop-oov`.
+pub fn frequencies_summary(frequencies_file: &Path) -> Result<Value, Box<dyn Error>> {
+    let reader = FrequenciesReader::open(frequencies_file)?;
+    let header = reader.header().clone();
+    let mut oov_count = None;
+    for entry in reader {
+        match entry? {
+            FrequenciesEntry::Unigram(token, count, _article_count) => {
+                if token == OUT_OF_VOCABULARY_WORD {
+                    oov_count = Some(count);
+                }
+            }
+            FrequenciesEntry::Bigram(..) => break,
+        }
+    }
+    Ok(json!({
+        "total_unigrams": header.total_unigrams,
+        "total_articles": header.total_articles,
+        "unigrams": header.unigrams,
+        "bigrams": header.bigrams,
+        "oov_rate": oov_count.map(|count| count as f64 / header.total_unigrams as f64),
+    }))
+}
@@ -0,0 +1,54 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+
+use crate::split::article_text;
+use crate::util::{open_input, InputCompression};
+
+/// Write `number_of_lines` article texts chosen uniformly at random from a cirrussearch dump to
+/// `output_file`, one per line. Uses reservoir sampling so the dump is read once and its length
+/// needn't be known in advance. Articles are written in reservoir order, not dump order.
+pub fn handle_sample(
+    input_path: &Path,
+    output_file: &Path,
+    number_of_lines: usize,
+    seed: u64,
+    input_compression: InputCompression,
+) -> Result<(), Box<dyn Error>> {
+    let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+    let (reader, _bytes_read) = open_input(input_path, input_compression)?;
+    let mut reservoir: Vec<String> = Vec::with_capacity(number_of_lines);
+    let mut articles_seen: u64 = 0;
+    for line in reader.lines() {
+        let text = match article_text(&line?)? {
+            Some(text) => text,
+            None => continue,
+        };
+        articles_seen += 1;
+        if reservoir.len() < number_of_lines {
+            reservoir.push(text);
+        } else {
+            let index = rng.gen_range(0, articles_seen) as usize;
+            if index < number_of_lines {
+                reservoir[index] = text;
+            }
+        }
+    }
+
+    let mut output = BufWriter::with_capacity(1024 * 1024, File::create(output_file)?);
+    for text in reservoir.iter() {
+        output.write_all(text.as_bytes())?;
+        output.write_all(b"\n")?;
+    }
+    output.flush()?;
+
+    println!(
+        "articles seen = {}, articles sampled = {}",
+        articles_seen,
+        reservoir.len()
+    );
+    Ok(())
+}
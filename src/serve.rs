@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use log::{info, warn};
+
+use crate::frequencies::{FrequenciesEntry, FrequenciesReader};
+
+/// Weight of the unigram probability of `w2` when the bigram `(w1, w2)` wasn't seen, as in the
+/// stupid backoff of Brants et al., "Large Language Models in Machine Translation", 2007.
+const BACKOFF_WEIGHT: f64 = 0.4;
+
+/// Counts loaded from a frequencies file, kept in memory to answer lookups. Bigram counts are
+/// nested by first word so that they can be looked up without allocating, and are only loaded
+/// if asked for, since they are usually much larger than the unigram counts.
+struct LookupModel {
+    total_unigrams: u64,
+    unigram_counts: HashMap<String, u64>,
+    bigram_counts: Option<HashMap<String, HashMap<String, u64>>>,
+}
+
+impl LookupModel {
+    fn load(input_file: &Path, load_bigrams: bool) -> Result<LookupModel, Box<dyn Error>> {
+        let reader = FrequenciesReader::open(input_file)?;
+        let total_unigrams = reader.header().total_unigrams;
+        if !load_bigrams {
+            let unigram_counts = reader
+                .unigrams()
+                .collect::<io::Result<HashMap<String, u64>>>()?;
+            return Ok(LookupModel {
+                total_unigrams,
+                unigram_counts,
+                bigram_counts: None,
+            });
+        }
+        let mut unigram_counts = HashMap::new();
+        let mut bigram_counts: HashMap<String, HashMap<String, u64>> = HashMap::new();
+        for entry in reader {
+            match entry? {
+                FrequenciesEntry::Unigram(token, count, _article_count) => {
+                    unigram_counts.insert(token, count);
+                }
+                FrequenciesEntry::Bigram(token1, token2, count) => {
+                    bigram_counts
+                        .entry(token1)
+                        .or_default()
+                        .insert(token2, count);
+                }
+            }
+        }
+        Ok(LookupModel {
+            total_unigrams,
+            unigram_counts,
+            bigram_counts: Some(bigram_counts),
+        })
+    }
+
+    fn unigram_count(&self, word: &str) -> u64 {
+        self.unigram_counts.get(word).copied().unwrap_or(0)
+    }
+
+    fn unigram_probability(&self, word: &str) -> f64 {
+        self.unigram_count(word) as f64 / self.total_unigrams as f64
+    }
+
+    /// The bigram count of `(word1, word2)` and P(word2 | word1), its count divided by the count
+    /// of `word1`, if the bigram is in the model. Otherwise a count of 0 and the unigram
+    /// probability of `word2` times `BACKOFF_WEIGHT`, which, as with any stupid backoff, is a
+    /// score rather than a normalized probability. The flag is whether the bigram was found.
+    fn bigram_estimate(
+        &self,
+        bigram_counts: &HashMap<String, HashMap<String, u64>>,
+        word1: &str,
+        word2: &str,
+    ) -> (u64, f64, bool) {
+        let count = bigram_counts
+            .get(word1)
+            .and_then(|successors| successors.get(word2))
+            .copied();
+        match (count, self.unigram_count(word1)) {
+            (Some(count), count1) if count1 > 0 => (count, count as f64 / count1 as f64, true),
+            _ => (0, BACKOFF_WEIGHT * self.unigram_probability(word2), false),
+        }
+    }
+
+    /// Answer every line of `reader`, which is a single word, with `word\tcount\tprobability`.
+    /// Words that aren't in the model have a count and probability of 0. If bigrams are loaded,
+    /// a line of two words `w1 w2` is answered with `w1\tw2\tcount\tprobability\tsource`, the
+    /// estimate of P(w2 | w1) from `bigram_estimate` and whether it's from the `bigram` or from
+    /// `backoff`.
+    fn serve_lines(&self, reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            let word = line.trim();
+            if word.is_empty() {
+                continue;
+            }
+            let words: Vec<&str> = word.split_whitespace().collect();
+            match (&self.bigram_counts, words.as_slice()) {
+                (Some(bigram_counts), [word1, word2]) => {
+                    let (count, probability, is_bigram) =
+                        self.bigram_estimate(bigram_counts, word1, word2);
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{}\t{:e}\t{}",
+                        word1,
+                        word2,
+                        count,
+                        probability,
+                        if is_bigram { "bigram" } else { "backoff" }
+                    )?;
+                }
+                _ => {
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{:e}",
+                        word,
+                        self.unigram_count(word),
+                        self.unigram_probability(word)
+                    )?;
+                }
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Load a frequencies file once, then answer word lookups over stdin/stdout or, if `listen` is
+/// given, over TCP connections to that address. The protocol is one word per line in, one
+/// `word\tcount\tprobability` line out, and with `bigrams` also two words per line in, one
+/// bigram estimate out, see `LookupModel::serve_lines`.
+pub fn handle_serve(
+    input_file: &Path,
+    listen: Option<&str>,
+    bigrams: bool,
+) -> Result<(), Box<dyn Error>> {
+    let model = Arc::new(LookupModel::load(input_file, bigrams)?);
+    match listen {
+        Some(address) => {
+            let listener = TcpListener::bind(address)?;
+            info!("listening on {}", listener.local_addr()?);
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let model = Arc::clone(&model);
+                thread::spawn(move || {
+                    let peer = stream
+                        .peer_addr()
+                        .map_or_else(|_| "unknown peer".to_string(), |peer| peer.to_string());
+                    let served = stream
+                        .try_clone()
+                        .and_then(|reader| model.serve_lines(BufReader::new(reader), stream));
+                    if let Err(err) = served {
+                        warn!("connection from {} failed: {}", peer, err);
+                    }
+                });
+            }
+            Ok(())
+        }
+        None => {
+            let stdin = io::stdin();
+            let stdout = io::stdout();
+            model.serve_lines(stdin.lock(), stdout.lock())?;
+            Ok(())
+        }
+    }
+}
@@ -1,77 +1,1005 @@
-use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use flate2::Compression;
 use flate2::GzBuilder;
+use log::{debug, info};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
+use serde_json::json;
+use std::collections::HashSet;
 use std::error::Error;
+use std::ffi::OsStr;
 use std::fs;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
 use std::io::{BufRead, BufWriter};
-use std::io::{BufReader, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use unicode_normalization::UnicodeNormalization;
 
+use crate::util::{gzip_member_filename, open_input, InputCompression, InputPiece};
+
+/// Name of the checkpoint file written into the output directory. Must not contain "split",
+/// otherwise `create-frequencies` would pick it up as an input file.
+const CHECKPOINT_FILENAME: &str = "checkpoint.json";
+
+/// Suffix of the index written next to a `--single-file` archive, see `write_single_file`.
+const SINGLE_FILE_INDEX_SUFFIX: &str = ".split.index.json";
+
+/// JSON pointer to the article text of a record in the cirrussearch dumps.
+pub const DEFAULT_TEXT_POINTER: &str = "/text";
+
+/// Seed of the RNG that assigns lines to pieces, fixed so that a split is reproducible.
+pub const SPLIT_SEED: u64 = 42;
+
+/// How many written lines between checkpoints. Each checkpoint finishes the current gzip member
+/// of every piece, so this shouldn't be too small. Tests checkpoint often so that small inputs
+/// can be resumed.
+#[cfg(not(test))]
+const CHECKPOINT_INTERVAL: u64 = 100_000;
+#[cfg(test)]
+const CHECKPOINT_INTERVAL: u64 = 100;
+
+type PieceWriter = GzEncoder<BufWriter<File>>;
+
+/// How `handle_split` assigns lines to pieces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PieceAssignment {
+    /// Each line to a random piece, so that every piece is a random sample of the dump.
+    Random,
+
+    /// Contiguous blocks in input order, each piece getting about an equal share of the input
+    /// by its compressed size, read so far, so that a piece holds neighbouring articles, e.g.
+    /// to narrow down a problem to part of the dump. Pieces are no longer random samples. The
+    /// position is that of the decompressor in the input file, which reads gzip input 32 KiB at a
+    /// time, so on inputs of only a few times that per piece the shares are uneven and pieces
+    /// can even be empty. The order of the pieces is always the input order.
+    Contiguous,
+
+    /// This many lines to each piece in input order, with as many pieces as needed.
+    Chunks(u64),
+}
+
+/// Where a previous, interrupted split got to. Pieces are valid gzip streams up to
+/// `piece_lengths`, anything after that is a partially written gzip member and is discarded.
+struct Checkpoint {
+    /// Number of lines consumed from the input, including lines without text.
+    lines_read: u64,
+
+    /// Number of lines written to pieces, which is also the number of RNG draws made.
+    lines_written: u64,
+
+    /// Byte length of each piece file at the time of the checkpoint.
+    piece_lengths: Vec<u64>,
+
+    /// The input and the options that decide which lines go to which piece, see
+    /// `checkpoint_settings`. A resumed split must have the same ones.
+    settings: serde_json::Value,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint in `output_dir`, if any, erroring if it was written by a split of
+    /// another number of `pieces` or with other `settings`, whose lines the resumed split would
+    /// append to the pieces inconsistently.
+    fn load(
+        output_dir: &Path,
+        pieces: u32,
+        settings: &serde_json::Value,
+    ) -> Result<Option<Checkpoint>, Box<dyn Error>> {
+        let checkpoint_path = output_dir.join(CHECKPOINT_FILENAME);
+        if !checkpoint_path.is_file() {
+            return Ok(None);
+        }
+        let checkpoint: serde_json::Value =
+            serde_json::from_str(fs::read_to_string(&checkpoint_path)?.as_str())?;
+        let invalid = || -> Box<dyn Error> {
+            format!("Invalid checkpoint file {:?}", checkpoint_path).into()
+        };
+        let lines_read = checkpoint["lines_read"].as_u64().ok_or_else(invalid)?;
+        let lines_written = checkpoint["lines_written"].as_u64().ok_or_else(invalid)?;
+        let piece_lengths = checkpoint["piece_lengths"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|length| length.as_u64().ok_or_else(invalid))
+            .collect::<Result<Vec<u64>, Box<dyn Error>>>()?;
+        if piece_lengths.len() != pieces as usize {
+            let err: Box<dyn Error> = format!(
+                "Checkpoint was written for {} pieces but {} were requested",
+                piece_lengths.len(),
+                pieces
+            )
+            .into();
+            return Err(err);
+        }
+        let settings_object = settings.as_object().ok_or_else(invalid)?;
+        for (key, value) in settings_object {
+            let checkpoint_value = &checkpoint["settings"][key];
+            if checkpoint_value != value {
+                let err: Box<dyn Error> = format!(
+                    "Checkpoint was written for {} {} but this split has {}, split again without \
+                     --resume or resume with the same input and options",
+                    key, checkpoint_value, value
+                )
+                .into();
+                return Err(err);
+            }
+        }
+        Ok(Some(Checkpoint {
+            lines_read,
+            lines_written,
+            piece_lengths,
+            settings: settings.clone(),
+        }))
+    }
+
+    fn persist(&self, output_dir: &Path) -> Result<(), Box<dyn Error>> {
+        let checkpoint = json!({
+            "lines_read": self.lines_read,
+            "lines_written": self.lines_written,
+            "piece_lengths": self.piece_lengths,
+            "settings": self.settings,
+        });
+
+        // Write then rename so that an interruption never leaves a half-written checkpoint.
+        let checkpoint_path = output_dir.join(CHECKPOINT_FILENAME);
+        let tmp_path = checkpoint_path.with_extension("json.tmp");
+        fs::write(&tmp_path, checkpoint.to_string())?;
+        fs::rename(&tmp_path, &checkpoint_path)?;
+        Ok(())
+    }
+}
+
+/// Split the article texts of a cirrussearch dump randomly into `pieces`. With `start_line` and
+/// `end_line`, 1-based and inclusive, only that range of input lines is split. A ranged split
+/// doesn't replay the RNG over the skipped lines, so its lines are assigned to different pieces
+/// than in a full split, and it is never checkpointed. With `single_file` the pieces are combined
+/// into one archive at the end, see `write_single_file`. Records whose page ID, from the index
+/// line before them, is in `excluded_ids` are skipped. Article text is read from `text_pointer`,
+/// see `parse_dump_line`. With `skip_redirects`, records that aren't articles are skipped, see
+/// `is_non_article`. See `PieceAssignment` for how lines are assigned to pieces other than
+/// randomly; with `PieceAssignment::Chunks`, `pieces` is ignored, and such a split can't be
+/// resumed, so it is never checkpointed. Returns the number of articles written, including those
+/// written before a resumed split was interrupted, and the number of pieces.
+#[allow(clippy::too_many_arguments)]
 pub fn handle_split(
     input_path: &Path,
     output_dir: &Path,
     pieces: u32,
-) -> Result<(), Box<dyn Error>> {
-    println!("handle_split entry");
+    assignment: PieceAssignment,
+    resume: bool,
+    input_compression: InputCompression,
+    start_line: u64,
+    end_line: Option<u64>,
+    single_file: bool,
+    excluded_ids: Option<&HashSet<String>>,
+    text_pointer: &str,
+    skip_redirects: bool,
+) -> Result<(u64, u32), Box<dyn Error>> {
+    debug!("handle_split entry");
+    if end_line.is_some_and(|end_line| end_line < start_line) {
+        let err: Box<dyn Error> = format!(
+            "End line {} is before start line {}",
+            end_line.unwrap(),
+            start_line
+        )
+        .into();
+        return Err(err);
+    }
+    let is_ranged = start_line > 1 || end_line.is_some();
+
+    let input_size = fs::metadata(input_path)?.len();
+    let settings = checkpoint_settings(
+        input_path,
+        input_size,
+        assignment,
+        input_compression,
+        excluded_ids,
+        text_pointer,
+        skip_redirects,
+    )?;
+    let checkpoint = if resume {
+        let checkpoint = Checkpoint::load(output_dir, pieces, &settings)?;
+        if checkpoint.is_none() {
+            info!("no checkpoint found, starting split from the beginning");
+        }
+        checkpoint
+    } else {
+        None
+    };
 
-    if output_dir.is_dir() {
-        println!("deleting output directory {}", output_dir.to_string_lossy());
-        fs::remove_dir_all(output_dir)?;
+    if checkpoint.is_none() {
+        if output_dir.is_dir() {
+            info!("deleting output directory {}", output_dir.to_string_lossy());
+            fs::remove_dir_all(output_dir)?;
+        }
+        fs::create_dir(output_dir)?;
     }
-    fs::create_dir(output_dir)?;
 
+    // Chunks are created as they fill up, only the current one being open.
     let mut output_files = Vec::with_capacity(pieces as usize);
-    let basename = input_path.file_stem().unwrap().to_string_lossy();
-    for i in 0..pieces {
-        let output_filename = format!("{}.split.{:03}", basename, i);
-        let output_filename_gz = format!("{}.gz", output_filename);
-        let output_path = Path::join(output_dir, output_filename_gz);
-        let output_file = File::create(&output_path).unwrap_or_else(|err| {
-            panic!(
-                "Could not create output file {:?} due to {:?}",
-                output_path, err
-            )
-        });
-        let output_file = BufWriter::with_capacity(1024 * 1024, output_file);
-        let output_file = GzBuilder::new()
-            .filename(output_filename)
-            .write(output_file, Compression::best());
-        output_files.push(output_file);
+    let basename = piece_basename(input_path);
+    if !matches!(assignment, PieceAssignment::Chunks(_)) {
+        for i in 0..pieces {
+            let resume_length = checkpoint
+                .as_ref()
+                .map(|checkpoint| checkpoint.piece_lengths[i as usize]);
+            output_files.push(create_piece(output_dir, &basename, i, resume_length)?);
+        }
     }
+    let mut chunks = 0;
 
-    let mut rng: StdRng = SeedableRng::seed_from_u64(42);
-    let reader = File::open(input_path)?;
-    let reader = GzDecoder::new(reader);
-    let reader = BufReader::new(reader);
+    let mut rng: StdRng = SeedableRng::seed_from_u64(SPLIT_SEED);
+    let (reader, bytes_read) = open_input(input_path, input_compression)?;
+    let mut lines = reader.lines();
+    let mut lines_read = 0;
     let mut i = 0;
-    for line in reader.lines() {
-        let line = line.unwrap();
-        let line_json: serde_json::Value = serde_json::from_str(line.as_str()).unwrap();
-        let text = line_json.get("text");
-        if text.is_none() {
+
+    if let Some(checkpoint) = &checkpoint {
+        info!(
+            "resuming split after {} input lines ({} written)",
+            checkpoint.lines_read, checkpoint.lines_written
+        );
+        for _ in 0..checkpoint.lines_read {
+            lines.next().transpose()?;
+        }
+
+        // Replay the RNG so that the remaining lines are assigned to the same pieces as they
+        // would have been in an uninterrupted run.
+        for _ in 0..checkpoint.lines_written {
+            rng.gen_range(0, pieces);
+        }
+        lines_read = checkpoint.lines_read;
+        i = checkpoint.lines_written;
+    }
+
+    if start_line > 1 {
+        info!("skipping to input line {}", start_line);
+        for _ in 1..start_line {
+            if lines.next().transpose()?.is_none() {
+                break;
+            }
+        }
+        lines_read = start_line - 1;
+    }
+
+    // ETA is from the rate since this run started, so skipped lines of a resumed split don't
+    // inflate it.
+    let start_time = Instant::now();
+    let start_bytes = bytes_read.get();
+    // Contiguous pieces share out the whole input, or only the range of a ranged split, which
+    // is never checkpointed. A resumed split carries on from where it got to in the input, so
+    // that the remaining lines go to the same pieces as in an uninterrupted run.
+    let range_start_bytes = if start_line > 1 { start_bytes } else { 0 };
+    let mut page_id = None;
+    let mut excluded_records = 0;
+    let mut skipped_records = 0;
+
+    for line in lines {
+        if end_line.is_some_and(|end_line| lines_read >= end_line) {
+            break;
+        }
+        let line = line?;
+        lines_read += 1;
+        let (text, namespace) = match parse_dump_line(&line, text_pointer)? {
+            DumpLine::Index(id) => {
+                page_id = id;
+                continue;
+            }
+            DumpLine::Article(text, namespace) => (text, namespace),
+            DumpLine::Other => continue,
+        };
+        // Each index line only applies to the record right after it.
+        if let (Some(excluded_ids), Some(id)) = (excluded_ids, page_id.take()) {
+            if excluded_ids.contains(&id) {
+                excluded_records += 1;
+                continue;
+            }
+        }
+        if skip_redirects && is_non_article(&text, namespace) {
+            skipped_records += 1;
             continue;
         }
-        let text = text.unwrap().as_str().unwrap();
-        let text = text.nfkc().collect::<String>();
-        let random_piece = rng.gen_range(0, pieces) as usize;
-        let output_file = &mut output_files[random_piece];
+        let piece = match assignment {
+            PieceAssignment::Chunks(chunk_lines) => {
+                if i % chunk_lines == 0 {
+                    if let Some((_, output_file)) = output_files.pop() {
+                        output_file.finish()?.flush()?;
+                    }
+                    output_files.push(create_piece(output_dir, &basename, chunks, None)?);
+                    chunks += 1;
+                }
+                0
+            }
+            PieceAssignment::Contiguous => contiguous_piece(
+                bytes_read.get() - range_start_bytes,
+                input_size - range_start_bytes,
+                pieces,
+            ),
+            PieceAssignment::Random => rng.gen_range(0, pieces) as usize,
+        };
+        let (_, output_file) = &mut output_files[piece];
         output_file.write_all(text.as_bytes())?;
         output_file.write_all(b"\n")?;
 
         i += 1;
         if i % 10000 == 0 {
-            println!("{}", i);
+            info!(
+                "{} {}",
+                i,
+                format_progress(bytes_read.get(), start_bytes, input_size, start_time)
+            );
+        }
+        let is_chunked = matches!(assignment, PieceAssignment::Chunks(_));
+        if !is_ranged && !is_chunked && i % CHECKPOINT_INTERVAL == 0 {
+            output_files = checkpoint_pieces(output_files, output_dir, lines_read, i, &settings)?;
         }
     }
 
-    for output_file in output_files {
+    for (_, output_file) in output_files {
         let mut inner = output_file.finish()?;
         inner.flush()?;
     }
+    if excluded_ids.is_some() {
+        info!("excluded {} records by page ID", excluded_records);
+    }
+    if skip_redirects {
+        info!(
+            "skipped {} redirect and non-article records",
+            skipped_records
+        );
+    }
+
+    let checkpoint_path = output_dir.join(CHECKPOINT_FILENAME);
+    if checkpoint_path.is_file() {
+        fs::remove_file(checkpoint_path)?;
+    }
+
+    let pieces = match assignment {
+        PieceAssignment::Chunks(_) => chunks,
+        PieceAssignment::Random | PieceAssignment::Contiguous => pieces,
+    };
+    if single_file {
+        write_single_file(output_dir, &basename, pieces)?;
+    }
+
+    Ok((i, pieces))
+}
+
+/// The piece of `pieces` for a line with `PieceAssignment::Contiguous`, when `bytes_read` of
+/// `input_size` bytes of input have been read: piece i gets the i-th of `pieces` equal shares.
+fn contiguous_piece(bytes_read: u64, input_size: u64, pieces: u32) -> usize {
+    if input_size == 0 {
+        return pieces as usize - 1;
+    }
+    let piece = bytes_read as u128 * pieces as u128 / input_size as u128;
+    piece.min(pieces as u128 - 1) as usize
+}
 
+/// Open piece `i` for writing, created empty, or with `resume_length` truncated to that length
+/// and appended to, returning its name and its writer.
+fn create_piece(
+    output_dir: &Path,
+    basename: &str,
+    i: u32,
+    resume_length: Option<u64>,
+) -> Result<(String, PieceWriter), Box<dyn Error>> {
+    let output_path = output_dir.join(format!("{}.split.{:03}.gz", basename, i));
+    let output_filename = gzip_member_filename(&output_path);
+    let output_file = match resume_length {
+        Some(length) => open_piece_for_resume(&output_path, length),
+        None => File::create(&output_path),
+    }
+    .map_err(|err| {
+        format!(
+            "Could not create output file {:?} due to {:?}",
+            output_path, err
+        )
+    })?;
+    let output_file = BufWriter::with_capacity(1024 * 1024, output_file);
+    let output_file = new_piece_writer(output_file, &output_filename);
+    Ok((output_filename, output_file))
+}
+
+/// Concatenate the pieces into one `BASENAME.split.gz` archive, a valid multi-member gzip file,
+/// and write an index of each piece's byte range in it so that `create-frequencies` can still
+/// count the pieces in parallel. The pieces are removed before the index is written, so an
+/// interrupted run never leaves lines that would be counted twice, at worst an archive without
+/// an index, which is counted as a single piece.
+fn write_single_file(output_dir: &Path, basename: &str, pieces: u32) -> Result<(), Box<dyn Error>> {
+    let archive_filename = format!("{}.split.gz", basename);
+    let archive_path = output_dir.join(&archive_filename);
+    info!("combining pieces into {:?}", archive_path);
+    let mut archive = BufWriter::with_capacity(1024 * 1024, File::create(&archive_path)?);
+    let mut offset = 0;
+    let mut index_pieces = Vec::with_capacity(pieces as usize);
+    let mut piece_paths = Vec::with_capacity(pieces as usize);
+    for i in 0..pieces {
+        let piece_name = format!("{}.split.{:03}", basename, i);
+        let piece_path = output_dir.join(format!("{}.gz", piece_name));
+        let length = io::copy(&mut File::open(&piece_path)?, &mut archive)?;
+        index_pieces.push(json!({
+            "name": piece_name,
+            "offset": offset,
+            "length": length,
+        }));
+        offset += length;
+        piece_paths.push(piece_path);
+    }
+    archive.flush()?;
+    archive.get_ref().sync_all()?;
+    for piece_path in piece_paths {
+        fs::remove_file(piece_path)?;
+    }
+
+    let index = json!({
+        "archive": archive_filename,
+        "pieces": index_pieces,
+    });
+    let index_path = output_dir.join(format!("{}{}", basename, SINGLE_FILE_INDEX_SUFFIX));
+    // Like the checkpoint, the temporary file must not contain "split".
+    let tmp_path = output_dir.join("index.json.tmp");
+    fs::write(&tmp_path, index.to_string())?;
+    fs::rename(&tmp_path, &index_path)?;
     Ok(())
 }
+
+/// Whether `path` is the index of a `--single-file` archive.
+pub fn is_single_file_index(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .is_some_and(|file_name| file_name.ends_with(SINGLE_FILE_INDEX_SUFFIX))
+}
+
+/// Load the index of a `--single-file` archive, returning the archive's path and its pieces.
+pub fn load_single_file_index(
+    index_path: &Path,
+) -> Result<(PathBuf, Vec<InputPiece>), Box<dyn Error>> {
+    let index: serde_json::Value = serde_json::from_str(fs::read_to_string(index_path)?.as_str())?;
+    let invalid = || -> Box<dyn Error> { format!("Invalid split index {:?}", index_path).into() };
+    let archive_filename = index["archive"].as_str().ok_or_else(invalid)?;
+    let archive_path = index_path.with_file_name(archive_filename);
+    let pieces = index["pieces"]
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|piece| {
+            Ok(InputPiece::archive_piece(
+                archive_path.clone(),
+                piece["name"].as_str().ok_or_else(invalid)?.to_string(),
+                piece["offset"].as_u64().ok_or_else(invalid)?,
+                piece["length"].as_u64().ok_or_else(invalid)?,
+            ))
+        })
+        .collect::<Result<Vec<InputPiece>, Box<dyn Error>>>()?;
+    Ok((archive_path, pieces))
+}
+
+/// One line of a cirrussearch dump, which alternates index lines and article records.
+#[derive(Debug, PartialEq)]
+pub enum DumpLine {
+    /// An index line, `{"index": {"_id": ...}}`, with the page ID of the record that follows it
+    /// as a string, if it has one.
+    Index(Option<String>),
+
+    /// An article record's NFKC-normalized text, and its `namespace` if it has one.
+    Article(String, Option<i64>),
+
+    /// Any other JSON line, including records without text.
+    Other,
+}
+
+/// Parse one line of a cirrussearch dump, reading the article text of a record from the field
+/// `text_pointer` points to, in JSON pointer syntax, e.g. `/text` or `/source_text`. The field may
+/// be a string or an array of strings, see `field_text`. Records where it points to nothing or
+/// to anything else aren't articles, since their text can't be counted, so dumps whose schema
+/// varies between records can still be split. Errors if the line isn't JSON.
+pub fn parse_dump_line(line: &str, text_pointer: &str) -> Result<DumpLine, Box<dyn Error>> {
+    let line_json: serde_json::Value = serde_json::from_str(line)?;
+    if let Some(index) = line_json.get("index") {
+        let id = match index.get("_id") {
+            Some(serde_json::Value::String(id)) => Some(id.clone()),
+            Some(serde_json::Value::Number(id)) => Some(id.to_string()),
+            _ => None,
+        };
+        return Ok(DumpLine::Index(id));
+    }
+    match pointed_text(&line_json, &[text_pointer]) {
+        Some(text) => Ok(DumpLine::Article(
+            text.nfkc().collect::<String>(),
+            line_json
+                .get("namespace")
+                .and_then(serde_json::Value::as_i64),
+        )),
+        None => Ok(DumpLine::Other),
+    }
+}
+
+/// The text of one record for `create-frequencies --input-json`: the string fields that
+/// `text_pointers` point to, joined by spaces, e.g. the title and the text of an article. Not
+/// normalized, `create-frequencies` does that itself. `None` for index lines and records without
+/// any of the fields. Errors if the line isn't JSON.
+pub fn record_text(line: &str, text_pointers: &[String]) -> Result<Option<String>, Box<dyn Error>> {
+    let line_json: serde_json::Value = serde_json::from_str(line)?;
+    if line_json.get("index").is_some() {
+        return Ok(None);
+    }
+    Ok(pointed_text(&line_json, text_pointers))
+}
+
+/// The text of the fields of `line_json` that `text_pointers` point to, in order and joined by
+/// spaces, or `None` if none of them has text, see `field_text`.
+fn pointed_text<P: AsRef<str>>(
+    line_json: &serde_json::Value,
+    text_pointers: &[P],
+) -> Option<String> {
+    let mut fields = text_pointers.iter().filter_map(|text_pointer| {
+        line_json
+            .pointer(text_pointer.as_ref())
+            .and_then(field_text)
+    });
+    let mut text = fields.next()?;
+    for field in fields {
+        text.push(' ');
+        text.push_str(&field);
+    }
+    Some(text)
+}
+
+/// The text of a field: a string as is, or the strings of an array, e.g. of paragraphs as some
+/// dumps store them, joined by spaces, other elements being skipped. `None` for anything else
+/// and for arrays without strings.
+fn field_text(field: &serde_json::Value) -> Option<String> {
+    match field {
+        serde_json::Value::String(text) => Some(text.clone()),
+        serde_json::Value::Array(elements) => {
+            let strings: Vec<&str> = elements
+                .iter()
+                .filter_map(serde_json::Value::as_str)
+                .collect();
+            if strings.is_empty() {
+                None
+            } else {
+                Some(strings.join(" "))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The NFKC-normalized `text` field of one line of a cirrussearch dump, or `None` for lines without
+/// one, i.e. the index lines that precede each article, or whose `text` is neither a string nor
+/// an array of strings. Errors if the line isn't JSON.
+pub fn article_text(line: &str) -> Result<Option<String>, Box<dyn Error>> {
+    match parse_dump_line(line, DEFAULT_TEXT_POINTER)? {
+        DumpLine::Article(text, _namespace) => Ok(Some(text)),
+        DumpLine::Index(_) | DumpLine::Other => Ok(None),
+    }
+}
+
+/// Whether a record is noise rather than an article: outside of the main namespace 0, e.g. a
+/// talk, user or category page, or without any text, as redirects and other stubs are. In
+/// cirrussearch dumps redirects are mostly listed in the `redirect` field of the page they point
+/// to rather than being records of their own, so the namespace is what usually matters.
+fn is_non_article(text: &str, namespace: Option<i64>) -> bool {
+    namespace.is_some_and(|namespace| namespace != 0) || text.trim().is_empty()
+}
+
+/// Page IDs to leave out of a split, one per line, e.g. those of a test set. Blank lines are
+/// ignored and surrounding whitespace trimmed.
+pub fn load_excluded_ids(path: &Path) -> Result<HashSet<String>, Box<dyn Error>> {
+    let ids = fs::read_to_string(path).map_err(|err| {
+        format!(
+            "Could not read excluded IDs file {:?} due to {:?}",
+            path, err
+        )
+    })?;
+    Ok(ids
+        .lines()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// The start of the piece file names for an input file: its file stem, made safe to use in a
+/// file name on every platform. Characters Windows reserves, control characters and path
+/// separators become `_`, trailing dots and spaces, which Windows drops, are removed, and a stem
+/// that is a reserved device name such as `CON` is prefixed with `_`, since `CON.split.000.gz`
+/// would still name the device on Windows.
+fn piece_basename(input_path: &Path) -> String {
+    const RESERVED_NAMES: [&str; 22] = [
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+    let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+    let sanitized: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let sanitized = sanitized.trim_end_matches(['.', ' ']);
+    let device_name = sanitized.split('.').next().unwrap_or_default();
+    if sanitized.is_empty() {
+        String::from("input")
+    } else if RESERVED_NAMES
+        .iter()
+        .any(|name| device_name.eq_ignore_ascii_case(name))
+    {
+        format!("_{}", sanitized)
+    } else {
+        sanitized.to_string()
+    }
+}
+
+/// Percentage of the input consumed and estimated time remaining, e.g. `(42.0%, ETA 1:02:03)`.
+fn format_progress(
+    bytes_read: u64,
+    start_bytes: u64,
+    input_size: u64,
+    start_time: Instant,
+) -> String {
+    if input_size == 0 {
+        return String::new();
+    }
+    let percent = 100.0 * bytes_read as f64 / input_size as f64;
+    let bytes_this_run = bytes_read.saturating_sub(start_bytes);
+    if bytes_this_run == 0 {
+        return format!("({:.1}%)", percent);
+    }
+    let remaining_bytes = input_size.saturating_sub(bytes_read);
+    let eta = start_time
+        .elapsed()
+        .mul_f64(remaining_bytes as f64 / bytes_this_run as f64);
+    format!("({:.1}%, ETA {})", percent, format_duration(eta))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn new_piece_writer(output_file: BufWriter<File>, output_filename: &str) -> PieceWriter {
+    GzBuilder::new()
+        .filename(output_filename)
+        .write(output_file, Compression::best())
+}
+
+/// Open an existing piece file for appending, discarding anything written after its checkpointed
+/// length. Further writes start a new gzip member, so the file is read back as a multi-member
+/// gzip stream.
+fn open_piece_for_resume(output_path: &Path, length: u64) -> std::io::Result<File> {
+    let output_file = OpenOptions::new().append(true).open(output_path)?;
+    output_file.set_len(length)?;
+    Ok(output_file)
+}
+
+/// What a checkpoint records about the split that wrote it: the input, by its absolute path and
+/// size, and the options that decide which lines are written and to which piece. The excluded
+/// page IDs are recorded by their number and a hash, see `excluded_ids_hash`.
+fn checkpoint_settings(
+    input_path: &Path,
+    input_size: u64,
+    assignment: PieceAssignment,
+    input_compression: InputCompression,
+    excluded_ids: Option<&HashSet<String>>,
+    text_pointer: &str,
+    skip_redirects: bool,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    Ok(json!({
+        "input": fs::canonicalize(input_path)?.to_string_lossy(),
+        "input size": input_size,
+        "seed": SPLIT_SEED,
+        "assignment": format!("{:?}", assignment),
+        "input compression": format!("{:?}", input_compression),
+        "excluded IDs": excluded_ids.map(|ids| json!({
+            "count": ids.len(),
+            "hash": excluded_ids_hash(ids).to_string(),
+        })),
+        "JSON pointer": text_pointer,
+        "skip redirects": skip_redirects,
+    }))
+}
+
+/// 64-bit FNV-1a hash of the sorted IDs, each followed by a newline. Unlike `DefaultHasher` it is
+/// the same for every build, so checkpoints can be resumed by another version.
+fn excluded_ids_hash(ids: &HashSet<String>) -> u64 {
+    let mut sorted_ids: Vec<&String> = ids.iter().collect();
+    sorted_ids.sort();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for id in sorted_ids {
+        for byte in id.bytes().chain(std::iter::once(b'\n')) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Finish the current gzip member of every piece so that everything written so far is durable
+/// and decodable, record the resulting file lengths in a checkpoint, then start new members.
+fn checkpoint_pieces(
+    output_files: Vec<(String, PieceWriter)>,
+    output_dir: &Path,
+    lines_read: u64,
+    lines_written: u64,
+    settings: &serde_json::Value,
+) -> Result<Vec<(String, PieceWriter)>, Box<dyn Error>> {
+    let mut piece_lengths = Vec::with_capacity(output_files.len());
+    let mut new_output_files = Vec::with_capacity(output_files.len());
+    for (output_filename, output_file) in output_files {
+        let mut inner = output_file.finish()?;
+        inner.flush()?;
+        piece_lengths.push(inner.get_ref().metadata()?.len());
+        let output_file = new_piece_writer(inner, &output_filename);
+        new_output_files.push((output_filename, output_file));
+    }
+    Checkpoint {
+        lines_read,
+        lines_written,
+        piece_lengths,
+        settings: settings.clone(),
+    }
+    .persist(output_dir)?;
+    Ok(new_output_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    fn dump_lines(articles: usize) -> Vec<String> {
+        (0..articles)
+            .flat_map(|i| {
+                vec![
+                    json!({"index": {"_id": i.to_string()}}).to_string(),
+                    json!({"text": format!("article {}", i)}).to_string(),
+                ]
+            })
+            .collect()
+    }
+
+    fn split_contiguous(
+        input_path: &Path,
+        output_dir: &Path,
+        resume: bool,
+    ) -> Result<(u64, u32), Box<dyn Error>> {
+        handle_split(
+            input_path,
+            output_dir,
+            4,
+            PieceAssignment::Contiguous,
+            resume,
+            InputCompression::Auto,
+            1,
+            None,
+            false,
+            None,
+            DEFAULT_TEXT_POINTER,
+            false,
+        )
+    }
+
+    fn piece_lines(output_dir: &Path, pieces: u32) -> Vec<Vec<String>> {
+        let basename = fs::read_dir(output_dir)
+            .unwrap()
+            .filter_map(|entry| {
+                let name = entry.unwrap().file_name().into_string().unwrap();
+                name.strip_suffix(".split.000.gz").map(String::from)
+            })
+            .next()
+            .unwrap();
+        (0..pieces)
+            .map(|i| {
+                let path = output_dir.join(format!("{}.split.{:03}.gz", basename, i));
+                let mut text = String::new();
+                MultiGzDecoder::new(File::open(path).unwrap())
+                    .read_to_string(&mut text)
+                    .unwrap();
+                text.lines().map(String::from).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resumed_contiguous_split_continues_into_later_pieces() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("dump.json");
+        let mut lines = dump_lines(2000);
+        let intact = lines.join("\n") + "\n";
+        // Interrupt the first run with an unparseable article after several checkpoints, of the
+        // same length as the intact one so that the resumed run sees the same input size.
+        let corrupt_line = 2 * 1500 + 1;
+        lines[corrupt_line] = lines[corrupt_line].replace('}', ",");
+        fs::write(&input_path, lines.join("\n") + "\n").unwrap();
+        let resumed_dir = dir.path().join("resumed");
+        assert!(split_contiguous(&input_path, &resumed_dir, false).is_err());
+        assert!(resumed_dir.join(CHECKPOINT_FILENAME).is_file());
+
+        fs::write(&input_path, &intact).unwrap();
+        assert_eq!(
+            split_contiguous(&input_path, &resumed_dir, true).unwrap(),
+            (2000, 4)
+        );
+        let uninterrupted_dir = dir.path().join("uninterrupted");
+        split_contiguous(&input_path, &uninterrupted_dir, false).unwrap();
+
+        let resumed = piece_lines(&resumed_dir, 4);
+        assert_eq!(resumed, piece_lines(&uninterrupted_dir, 4));
+        let expected: Vec<String> = (0..2000).map(|i| format!("article {}", i)).collect();
+        assert_eq!(resumed.concat(), expected);
+    }
+
+    #[test]
+    fn contiguous_split_of_small_gzip_input_keeps_input_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("dump.json.gz");
+        let mut encoder =
+            GzBuilder::new().write(File::create(&input_path).unwrap(), Compression::default());
+        for line in dump_lines(2000) {
+            writeln!(encoder, "{}", line).unwrap();
+        }
+        encoder.finish().unwrap();
+        let output_dir = dir.path().join("out");
+        assert_eq!(
+            split_contiguous(&input_path, &output_dir, false).unwrap(),
+            (2000, 4)
+        );
+
+        // The input is only a few reads of the decompressor, so some pieces may be empty, but
+        // the pieces are still contiguous blocks in input order.
+        let expected: Vec<String> = (0..2000).map(|i| format!("article {}", i)).collect();
+        assert_eq!(piece_lines(&output_dir, 4).concat(), expected);
+    }
+
+    // Backslashes are path separators on Windows and non-UTF-8 names are built from Unix bytes.
+    #[cfg(unix)]
+    #[test]
+    fn piece_basename_is_safe_for_problematic_input_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let cases: [(&Path, &str); 7] = [
+            (Path::new("dumps/plwiki.json.gz"), "plwiki.json"),
+            (
+                Path::new("dumps/back\\slash:colon?.json"),
+                "back_slash_colon_",
+            ),
+            (Path::new("dumps/.."), "input"),
+            (Path::new("dumps/..."), "input"),
+            (Path::new("dumps/trailing. .gz"), "trailing"),
+            (Path::new("dumps/con.json.gz"), "_con.json"),
+            (
+                Path::new(OsStr::from_bytes(b"dumps/caf\xe9.json.gz")),
+                "caf\u{fffd}.json",
+            ),
+        ];
+        for (input_path, expected) in &cases {
+            let basename = piece_basename(input_path);
+            assert_eq!(basename, *expected, "{:?}", input_path);
+            assert!(!basename.contains('/') && basename != ".." && basename != ".");
+        }
+    }
+
+    #[test]
+    fn text_may_be_a_string_or_an_array_of_strings() {
+        let cases = [
+            (
+                json!({"text": "Ala ma kota", "namespace": 0}),
+                DumpLine::Article(String::from("Ala ma kota"), Some(0)),
+            ),
+            (
+                json!({"text": ["Ala ma", 1, "kota", null]}),
+                DumpLine::Article(String::from("Ala ma kota"), None),
+            ),
+            (json!({"text": [1, 2]}), DumpLine::Other),
+            (json!({"text": 1}), DumpLine::Other),
+        ];
+        for (line, expected) in &cases {
+            assert_eq!(
+                parse_dump_line(&line.to_string(), DEFAULT_TEXT_POINTER).unwrap(),
+                *expected,
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn pieces_embed_their_file_name_in_the_gzip_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("dump.json");
+        fs::write(&input_path, dump_lines(10).join("\n") + "\n").unwrap();
+        let output_dir = dir.path().join("out");
+        split_contiguous(&input_path, &output_dir, false).unwrap();
+        for i in 0..4 {
+            let piece_name = format!("dump.split.{:03}", i);
+            let piece_file = File::open(output_dir.join(format!("{}.gz", piece_name))).unwrap();
+            let mut decoder = flate2::read::GzDecoder::new(piece_file);
+            io::copy(&mut decoder, &mut io::sink()).unwrap();
+            assert_eq!(
+                decoder.header().and_then(|header| header.filename()),
+                Some(piece_name.as_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn resuming_with_other_input_or_options_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("dump.json");
+        let mut lines = dump_lines(500);
+        let intact = lines.join("\n") + "\n";
+        let corrupt_line = 2 * 300 + 1;
+        lines[corrupt_line] = lines[corrupt_line].replace('}', ",");
+        fs::write(&input_path, lines.join("\n") + "\n").unwrap();
+        let output_dir = dir.path().join("out");
+        assert!(split_contiguous(&input_path, &output_dir, false).is_err());
+        fs::write(&input_path, &intact).unwrap();
+
+        let excluded_ids: HashSet<String> = vec![String::from("3")].into_iter().collect();
+        let resume = |assignment, excluded_ids, text_pointer, skip_redirects| {
+            handle_split(
+                &input_path,
+                &output_dir,
+                4,
+                assignment,
+                true,
+                InputCompression::Auto,
+                1,
+                None,
+                false,
+                excluded_ids,
+                text_pointer,
+                skip_redirects,
+            )
+        };
+        let mismatches = [
+            resume(PieceAssignment::Random, None, DEFAULT_TEXT_POINTER, false),
+            resume(
+                PieceAssignment::Contiguous,
+                Some(&excluded_ids),
+                DEFAULT_TEXT_POINTER,
+                false,
+            ),
+            resume(PieceAssignment::Contiguous, None, "/source_text", false),
+            resume(
+                PieceAssignment::Contiguous,
+                None,
+                DEFAULT_TEXT_POINTER,
+                true,
+            ),
+        ];
+        for result in &mismatches {
+            let err = result.as_ref().unwrap_err().to_string();
+            assert!(err.starts_with("Checkpoint was written for"), "{}", err);
+        }
+
+        fs::write(&input_path, intact.clone() + &intact).unwrap();
+        let err = split_contiguous(&input_path, &output_dir, true).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Checkpoint was written for input size"),
+            "{}",
+            err
+        );
+
+        fs::write(&input_path, &intact).unwrap();
+        assert_eq!(
+            split_contiguous(&input_path, &output_dir, true).unwrap(),
+            (500, 4)
+        );
+    }
+}
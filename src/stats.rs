@@ -0,0 +1,172 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use crate::frequencies::{FrequenciesEntry, FrequenciesReader};
+use crate::topkwords;
+
+/// Print summary statistics for a frequencies file. With `confidence_z` also print, for every
+/// unigram, its probability and a Wilson score interval for it at that z-score. With
+/// `entropy_words` also print that many of the words with the highest and lowest following-word
+/// entropy. With `successor_words` also print the number of distinct successors of that many of
+/// the most frequent words, see `print_distinct_successors`. With `per_million` also print every
+/// unigram's frequency per million words.
+pub fn handle_stats(
+    input_file: &Path,
+    confidence_z: Option<f64>,
+    entropy_words: Option<usize>,
+    successor_words: Option<usize>,
+    per_million: bool,
+) -> Result<(), Box<dyn Error>> {
+    let reader = FrequenciesReader::open(input_file)?;
+    let total_unigrams = reader.header().total_unigrams;
+    let total_articles = reader.header().total_articles;
+    let mut unigrams = Vec::new();
+    let mut bigrams = 0;
+    let mut successor_counts: HashMap<String, Vec<u64>> = HashMap::new();
+    for entry in reader {
+        match entry? {
+            FrequenciesEntry::Unigram(token, count, _article_count) => {
+                unigrams.push((token, count))
+            }
+            FrequenciesEntry::Bigram(token1, _token2, count) => {
+                bigrams += 1;
+                if entropy_words.is_some() || successor_words.is_some() {
+                    successor_counts.entry(token1).or_default().push(count);
+                }
+            }
+        }
+    }
+
+    println!("total unigrams = {}", total_unigrams);
+    match total_articles {
+        Some(total_articles) => println!("total articles = {}", total_articles),
+        None => println!("total articles = unknown, file predates article counting"),
+    }
+    println!("unigrams written = {}", unigrams.len());
+    println!("bigrams written = {}", bigrams);
+
+    if let Some(n) = entropy_words {
+        print_following_entropy(&unigrams, &successor_counts, n);
+    }
+
+    unigrams.sort_by_key(|(_word, count)| Reverse(*count));
+
+    if let Some(n) = successor_words {
+        print_distinct_successors(&unigrams, &successor_counts, n);
+    }
+
+    if per_million {
+        println!();
+        println!("word\tfreq_per_million");
+        for (word, count) in unigrams.iter() {
+            println!(
+                "{}\t{:.3}",
+                word,
+                topkwords::per_million(*count, total_unigrams)
+            );
+        }
+    }
+
+    if let Some(z) = confidence_z {
+        println!();
+        println!("word\tcount\tprobability\tlower\tupper");
+        for (word, count) in unigrams {
+            let (lower, upper) = wilson_interval(count, total_unigrams, z);
+            println!(
+                "{}\t{}\t{:.6e}\t{:.6e}\t{:.6e}",
+                word,
+                count,
+                count as f64 / total_unigrams as f64,
+                lower,
+                upper
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the `n` words with the highest and the `n` with the lowest following-word entropy, in
+/// bits. A word's following-word entropy is the sum of -p log2 p over its successors, where p is
+/// the bigram count divided by the word's unigram count. Low entropy means the word predicts its
+/// successor well, e.g. "San" is nearly always followed by "Francisco". Words without a unigram
+/// count in the file, i.e. those filtered out by `create-frequencies`, are skipped.
+fn print_following_entropy(
+    unigrams: &[(String, u64)],
+    successor_counts: &HashMap<String, Vec<u64>>,
+    n: usize,
+) {
+    let mut entropies: Vec<(&str, f64)> = unigrams
+        .iter()
+        .filter_map(|(word, count)| {
+            let successors = successor_counts.get(word)?;
+            let entropy = successors
+                .iter()
+                .map(|successor_count| {
+                    let p = *successor_count as f64 / *count as f64;
+                    -p * p.log2()
+                })
+                .sum();
+            Some((word.as_str(), entropy))
+        })
+        .collect();
+    entropies.sort_by(|(word1, entropy1), (word2, entropy2)| {
+        entropy1.total_cmp(entropy2).then_with(|| word1.cmp(word2))
+    });
+
+    println!();
+    println!("highest following-word entropy (bits)");
+    for (word, entropy) in entropies.iter().rev().take(n) {
+        println!("{}\t{:.4}", word, entropy);
+    }
+    println!();
+    println!("lowest following-word entropy (bits)");
+    for (word, entropy) in entropies.iter().take(n) {
+        println!("{}\t{:.4}", word, entropy);
+    }
+}
+
+/// Print the number of distinct words that follow each of the `n` most frequent of `unigrams`,
+/// which must be sorted by count, and the word's count, most distinct successors first. Function
+/// words such as "the" are followed by many different words, while words with a fixed
+/// continuation, such as "San", by few. Only the bigrams written to the file count, so bigrams
+/// filtered out by `create-frequencies` are missing.
+fn print_distinct_successors(
+    unigrams: &[(String, u64)],
+    successor_counts: &HashMap<String, Vec<u64>>,
+    n: usize,
+) {
+    let mut diversities: Vec<(&str, usize, u64)> = unigrams
+        .iter()
+        .take(n)
+        .map(|(word, count)| {
+            let successors = successor_counts.get(word).map_or(0, Vec::len);
+            (word.as_str(), successors, *count)
+        })
+        .collect();
+    diversities
+        .sort_by_key(|(word, successors, count)| (Reverse(*successors), Reverse(*count), *word));
+
+    println!();
+    println!("word\tdistinct_successors\tcount");
+    for (word, successors, count) in diversities {
+        println!("{}\t{}\t{}", word, successors, count);
+    }
+}
+
+/// Wilson score interval for a binomial proportion of `count` successes in `total` trials. Unlike
+/// the normal approximation it stays within [0, 1] and behaves well for rare words.
+fn wilson_interval(count: u64, total: u64, z: f64) -> (f64, f64) {
+    let n = total as f64;
+    let p = count as f64 / n;
+    let z2 = z * z;
+    let denominator = 1.0 + z2 / n;
+    let center = (p + z2 / (2.0 * n)) / denominator;
+    let half_width = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denominator;
+    (
+        (center - half_width).max(0.0),
+        (center + half_width).min(1.0),
+    )
+}
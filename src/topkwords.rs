@@ -1,72 +1,526 @@
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::util::{LineIterator, OUT_OF_VOCABULARY_WORD};
+use crate::frequencies::{FrequenciesEntry, FrequenciesReader};
+use crate::util::{collation_key, OUT_OF_VOCABULARY_WORD};
 use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::ops::Deref;
 
+use log::{debug, info};
+
+/// What, if anything, to write after each word.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputColumn {
+    None,
+
+    /// Frequency per million words.
+    PerMillion,
+
+    /// Inverse document frequency, ln(total articles / article count).
+    Idf,
+
+    /// The count, the article count and their ratio, the average number of occurrences in each
+    /// article the word is in, see `burstiness`.
+    Burstiness,
+}
+
+/// Prior weights of words, e.g. from a weighted dictionary, to blend with corpus frequencies when
+/// ranking, see `blend_with_priors`.
+pub struct Priors {
+    pub weights: HashMap<String, f64>,
+
+    /// How much the prior counts, from 0 for only the corpus frequency to 1 for only the prior.
+    pub blend: f64,
+}
+
+/// How `top-k-words --with-rank` writes ranks, e.g. zero-padded to a fixed width. They are ranks
+/// by frequency, or line numbers with `--stable-against`, and so only stable IDs with the
+/// latter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RankFormat {
+    /// The number written for the first-ranked word, the others numbered consecutively from it.
+    pub base: u64,
+
+    /// Zero-pad ranks to this many digits, erroring if the largest doesn't fit. 0 for no padding.
+    pub width: usize,
+}
+
+impl RankFormat {
+    /// Each of the 1-based `ranks` as written, erroring if one overflows or doesn't fit in `width`.
+    fn format(&self, ranks: &[usize]) -> Result<Vec<String>, Box<dyn Error>> {
+        let last_id = self
+            .base
+            .checked_add(ranks.len().saturating_sub(1) as u64)
+            .ok_or("Ranks overflow a 64-bit integer, pass a smaller --id-base")?;
+        if self.width > 0 && last_id.to_string().len() > self.width {
+            let err: Box<dyn Error> = format!(
+                "Ranks up to {} don't fit in {} digits, pass a larger --id-width",
+                last_id, self.width
+            )
+            .into();
+            return Err(err);
+        }
+        Ok(ranks
+            .iter()
+            .map(|rank| {
+                format!(
+                    "{:0width$}",
+                    self.base + *rank as u64 - 1,
+                    width = self.width
+                )
+            })
+            .collect())
+    }
+}
+
+/// Write the top `number_of_words` words of at least a minimum length to each output file, given
+/// as pairs of the file and its minimum length. Words are loaded and ranked once for all outputs,
+/// only the filtering by length is per output. Alphabetical order is that of the language code
+/// `collation`, or code-point order if it's empty, see `util::collation_key`. With `with_rank`
+/// each line starts with the word's rank by frequency among the words of its output, formatted
+/// as in `RankFormat`, whatever order they are written in. With `include_ties`, words with the
+/// same count as the last of the top `number_of_words` are included too, rather than cut off
+/// arbitrarily. With `stable_against`, a previous list of words, the order of that list is kept,
+/// see `stable_order`.
+#[allow(clippy::too_many_arguments)]
 pub fn handle_top_k_words(
     input_file: &Path,
-    output_file: &Path,
-    minimum_word_length: usize,
+    outputs: &[(PathBuf, usize)],
     number_of_words: usize,
+    include_ties: bool,
+    sort_alphabetically: bool,
+    collation: &str,
+    output_column: OutputColumn,
+    with_rank: Option<RankFormat>,
+    lowercase_output: bool,
+    priors: Option<&Priors>,
+    stable_against: Option<&[String]>,
 ) -> Result<(), Box<dyn Error>> {
-    let onegrams = load_sorted_onegrams(input_file).unwrap();
-    let top_onegrams: Vec<String> = onegrams
+    let mut onegrams = load_sorted_onegrams(input_file)?;
+    if lowercase_output {
+        onegrams = lowercase_onegrams(onegrams);
+    }
+    if let Some(priors) = priors {
+        let total_unigrams = FrequenciesReader::open(input_file)?.header().total_unigrams;
+        onegrams = blend_with_priors(onegrams, total_unigrams, priors);
+    }
+    let total_unigrams = match output_column {
+        OutputColumn::PerMillion => FrequenciesReader::open(input_file)?.header().total_unigrams,
+        _ => 0,
+    };
+    let (total_articles, article_counts) = match output_column {
+        OutputColumn::Idf | OutputColumn::Burstiness => load_article_counts(input_file)?,
+        _ => (0, HashMap::new()),
+    };
+    for (output_file, minimum_word_length) in outputs {
+        let mut long_onegrams = onegrams
+            .iter()
+            .filter(|(word, _count)| word.len() >= *minimum_word_length)
+            .peekable();
+        let mut top_onegrams: Vec<(String, u64)> = long_onegrams
+            .by_ref()
+            .take(number_of_words)
+            .cloned()
+            .collect();
+        if let (true, Some((_word, last_count))) = (include_ties, top_onegrams.last().cloned()) {
+            while let Some(onegram) = long_onegrams.next_if(|(_word, count)| *count == last_count) {
+                top_onegrams.push(onegram.clone());
+            }
+            if top_onegrams.len() > number_of_words {
+                info!(
+                    "writing {} words to {:?}, {} more than asked for because they are tied at \
+                     count {}",
+                    top_onegrams.len(),
+                    output_file,
+                    top_onegrams.len() - number_of_words,
+                    last_count
+                );
+            }
+        }
+        let mut ranked_onegrams: Vec<(usize, (String, u64))> = top_onegrams
+            .into_iter()
+            .enumerate()
+            .map(|(i, onegram)| (i + 1, onegram))
+            .collect();
+        if let Some(previous_words) = stable_against {
+            ranked_onegrams = stable_order(ranked_onegrams, previous_words, output_file);
+        }
+        if sort_alphabetically {
+            // Frequency still decides which words are included, this only changes the written
+            // order. Case-insensitive so that capitalised words sit next to their lowercase forms.
+            ranked_onegrams
+                .sort_by_cached_key(|(_rank, (word, _count))| collation_key(word, collation));
+        }
+        let (ranks, top_onegrams): (Vec<usize>, Vec<(String, u64)>) =
+            ranked_onegrams.into_iter().unzip();
+        let values = match output_column {
+            OutputColumn::None => None,
+            OutputColumn::PerMillion => {
+                let values = top_onegrams
+                    .iter()
+                    .map(|(_word, count)| format!("{:.3}", per_million(*count, total_unigrams)))
+                    .collect();
+                Some(values)
+            }
+            OutputColumn::Idf => {
+                let values = top_onegrams
+                    .iter()
+                    .map(|(word, _count)| {
+                        format!("{:.3}", idf(total_articles, article_counts[word]))
+                    })
+                    .collect();
+                Some(values)
+            }
+            OutputColumn::Burstiness => {
+                let values = top_onegrams
+                    .iter()
+                    .map(|(word, count)| {
+                        let article_count = article_counts[word];
+                        format!(
+                            "{}\t{}\t{:.3}",
+                            count,
+                            article_count,
+                            burstiness(*count, article_count)
+                        )
+                    })
+                    .collect();
+                Some(values)
+            }
+        };
+        let ranks = match with_rank {
+            Some(rank_format) => Some(rank_format.format(&ranks)?),
+            None => None,
+        };
+        write_sorted_onegrams_to_file(top_onegrams, ranks, values, output_file)?;
+    }
+    Ok(())
+}
+
+/// The output file of `top-k-words --min-lengths` for one minimum length, `.minN` inserted before
+/// the extension of `output_file`, e.g. `out.min3.txt` for `out.txt`.
+pub fn min_length_output_file(output_file: &Path, minimum_word_length: usize) -> PathBuf {
+    let stem = output_file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = match output_file.extension() {
+        Some(extension) => format!(
+            "{}.min{}.{}",
+            stem,
+            minimum_word_length,
+            extension.to_string_lossy()
+        ),
+        None => format!("{}.min{}", stem, minimum_word_length),
+    };
+    output_file.with_file_name(file_name)
+}
+
+/// Order the ranked top words so that word IDs, i.e. line numbers, change as little as possible
+/// from `previous_words`: words that are still in the top come first, in their previous order,
+/// followed by the words that are new to it, by rank. Words that dropped out are removed, so
+/// the words after them still move up. The words are then ranked by their new position rather
+/// than by frequency, so that `--with-rank` writes their line numbers. Logs how many words were
+/// kept, added and removed, and at debug level which.
+fn stable_order(
+    ranked_onegrams: Vec<(usize, (String, u64))>,
+    previous_words: &[String],
+    output_file: &Path,
+) -> Vec<(usize, (String, u64))> {
+    let previous_positions: HashMap<&str, usize> = previous_words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| (word.as_str(), i))
+        .collect();
+    let (mut kept, added): (Vec<_>, Vec<_>) = ranked_onegrams
         .into_iter()
-        .map(|(word, _count)| word)
-        .filter(|word| word.len() >= minimum_word_length)
-        .take(number_of_words)
+        .partition(|(_rank, (word, _count))| previous_positions.contains_key(word.as_str()));
+    kept.sort_by_key(|(_rank, (word, _count))| previous_positions[word.as_str()]);
+    let kept_words: HashSet<&str> = kept
+        .iter()
+        .map(|(_rank, (word, _count))| word.as_str())
         .collect();
-    write_sorted_onegrams_to_file(top_onegrams, output_file).unwrap();
-    Ok(())
+    let removed: Vec<&String> = previous_words
+        .iter()
+        .filter(|word| !kept_words.contains(word.as_str()))
+        .collect();
+    info!(
+        "{:?}: kept {} words of the previous list in their order, added {}, removed {}",
+        output_file,
+        kept.len(),
+        added.len(),
+        removed.len()
+    );
+    for (_rank, (word, _count)) in added.iter() {
+        debug!("added {}", word);
+    }
+    for word in removed {
+        debug!("removed {}", word);
+    }
+    kept.extend(added);
+    kept.into_iter()
+        .enumerate()
+        .map(|(i, (_rank, onegram))| (i + 1, onegram))
+        .collect()
 }
 
-fn load_sorted_onegrams(input_file: &Path) -> Result<Vec<(String, u64)>, Box<dyn Error>> {
-    let mut result = Vec::new();
-    let mut loading_onegrams = false;
-    for line in LineIterator::new(input_file).unwrap() {
-        let line_borrowed = line.borrow();
-        let line_borrowed = line_borrowed.deref();
-        if line_borrowed.starts_with("\\1-grams:") {
-            loading_onegrams = true;
-            continue;
-        }
-        if !loading_onegrams {
-            continue;
-        }
-        if line_borrowed.trim_end().is_empty() {
-            break;
+/// The words of a list written by `top-k-words`, in order: the first tab-separated field of every
+/// non-empty line, or the second if the lines start with numbers counting up by 1 followed by
+/// another field, i.e. the list was written with `--with-rank`. A list of numeric words with a
+/// column such as `--per-million`'s is only mistaken for that if its words are consecutive too.
+pub fn load_word_list(input_file: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let contents = fs::read_to_string(input_file)
+        .map_err(|err| format!("Could not read word list {:?} due to {:?}", input_file, err))?;
+    let rows: Vec<Vec<&str>> = contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.split('\t').collect())
+        .collect();
+    let ranks: Option<Vec<u64>> = rows
+        .iter()
+        .map(|fields| {
+            if fields.len() >= 2 && fields[0].bytes().all(|byte| byte.is_ascii_digit()) {
+                fields[0].parse().ok()
+            } else {
+                None
+            }
+        })
+        .collect();
+    let has_ranks = ranks.is_some_and(|ranks| {
+        !ranks.is_empty()
+            && ranks
+                .windows(2)
+                .all(|pair| pair[0].checked_add(1) == Some(pair[1]))
+    });
+    let word_column = if has_ranks { 1 } else { 0 };
+    Ok(rows
+        .into_iter()
+        .map(|fields| fields[word_column].to_string())
+        .collect())
+}
+
+/// Inverse document frequency of a word that is in `article_count` of `total_articles` articles.
+/// Infinite for a word without an article count, which can only be the last token of lines.
+/// A difference of logarithms, as in `ln_probability`.
+pub fn idf(total_articles: u64, article_count: u64) -> f64 {
+    (total_articles as f64).ln() - (article_count as f64).ln()
+}
+
+/// Average number of occurrences of a word in each of the articles it is in, its count divided by
+/// its article count. 1 for a word that never repeats within an article, high for jargon that
+/// clusters in a few articles on its topic. Infinite for a word without an article count, as for
+/// `idf`.
+pub fn burstiness(count: u64, article_count: u64) -> f64 {
+    count as f64 / article_count as f64
+}
+
+/// The total number of articles and every unigram's article count, erroring if the file wasn't
+/// written with article counts.
+fn load_article_counts(input_file: &Path) -> Result<(u64, HashMap<String, u64>), Box<dyn Error>> {
+    let reader = FrequenciesReader::open(input_file)?;
+    let missing_article_counts = || -> Box<dyn Error> {
+        format!(
+            "No article counts in {:?}, create it with 'create-frequencies --article-counts'",
+            input_file
+        )
+        .into()
+    };
+    let total_articles = reader
+        .header()
+        .total_articles
+        .ok_or_else(missing_article_counts)?;
+    let mut article_counts = HashMap::new();
+    for entry in reader {
+        match entry? {
+            FrequenciesEntry::Unigram(token, _count, Some(article_count)) => {
+                article_counts.insert(token, article_count);
+            }
+            FrequenciesEntry::Unigram(_token, _count, None) => {
+                return Err(missing_article_counts());
+            }
+            FrequenciesEntry::Bigram(..) => break,
         }
-        let elems: Vec<&str> = line_borrowed.split("\t").collect();
-        let count: u64 = elems[0].parse().expect("Needed a number");
-        let token = elems[1].trim_end();
+    }
+    Ok((total_articles, article_counts))
+}
+
+/// Occurrences per million words, the usual unit for word frequencies in corpus linguistics. A
+/// single division, so as precise as the counts cast to `f64`, see `ln_probability`.
+pub fn per_million(count: u64, total_unigrams: u64) -> f64 {
+    count as f64 / total_unigrams as f64 * 1_000_000.0
+}
+
+/// Sort words by `(1 - blend) * P_corpus(word) + blend * P_prior(word)`, where P_corpus is the
+/// count divided by `total_unigrams` and P_prior the word's weight divided by the sum of the
+/// weights, so that words that matter are ranked higher even if rare in the corpus. Words
+/// without a weight have a prior of 0. Only words in the corpus are ranked. Ties are broken by
+/// count, then alphabetically. With equal weights for every word the order is by count, as
+/// without priors.
+fn blend_with_priors(
+    onegrams: Vec<(String, u64)>,
+    total_unigrams: u64,
+    priors: &Priors,
+) -> Vec<(String, u64)> {
+    let total_weight: f64 = priors.weights.values().sum();
+    let score = |word: &str, count: u64| {
+        let corpus_probability = count as f64 / total_unigrams as f64;
+        let prior_probability = if total_weight > 0.0 {
+            priors.weights.get(word).copied().unwrap_or(0.0) / total_weight
+        } else {
+            0.0
+        };
+        (1.0 - priors.blend) * corpus_probability + priors.blend * prior_probability
+    };
+    let mut scored: Vec<(f64, String, u64)> = onegrams
+        .into_iter()
+        .map(|(word, count)| (score(&word, count), word, count))
+        .collect();
+    scored.sort_by(|(score1, word1, count1), (score2, word2, count2)| {
+        score2
+            .total_cmp(score1)
+            .then_with(|| count2.cmp(count1))
+            .then_with(|| word1.cmp(word2))
+    });
+    scored
+        .into_iter()
+        .map(|(_score, word, count)| (word, count))
+        .collect()
+}
+
+/// Lowercase every word, summing the counts of words that are the same once lowercased, e.g.
+/// "The" and "the", and sort the result by count again. Ties are broken alphabetically.
+fn lowercase_onegrams(onegrams: Vec<(String, u64)>) -> Vec<(String, u64)> {
+    let mut counts: HashMap<String, u64> = HashMap::with_capacity(onegrams.len());
+    for (word, count) in onegrams {
+        *counts.entry(word.to_lowercase()).or_insert(0) += count;
+    }
+    let mut result: Vec<(String, u64)> = counts.into_iter().collect();
+    result.sort_by(|(word1, count1), (word2, count2)| {
+        count2.cmp(count1).then_with(|| word1.cmp(word2))
+    });
+    result
+}
+
+pub fn load_sorted_onegrams(input_file: &Path) -> Result<Vec<(String, u64)>, Box<dyn Error>> {
+    let mut result = Vec::new();
+    for unigram in FrequenciesReader::open(input_file)?.unigrams() {
+        let (token, count) = unigram?;
         if token == OUT_OF_VOCABULARY_WORD {
             continue;
         }
-        result.push((token.to_string(), count));
+        result.push((token, count));
     }
     result.sort_by_key(|(_word, count)| Reverse(*count));
 
     Ok(result)
 }
 
+/// Write one word per line or, given formatted `values` for each word, `word\tvalue` lines.
 fn write_sorted_onegrams_to_file(
-    top_onegrams: Vec<String>,
+    top_onegrams: Vec<(String, u64)>,
+    ranks: Option<Vec<String>>,
+    values: Option<Vec<String>>,
     output_file_path: &Path,
 ) -> Result<(), Box<dyn Error>> {
-    let output_file = File::create(output_file_path).unwrap_or_else(|err| {
-        panic!(
+    let output_file = File::create(output_file_path).map_err(|err| {
+        format!(
             "Could not create output file {:?} due to {:?}",
             output_file_path, err
         )
-    });
+    })?;
     let mut output_file = BufWriter::new(output_file);
-    for onegram in top_onegrams {
+    for (i, (onegram, _count)) in top_onegrams.into_iter().enumerate() {
+        if let Some(ranks) = &ranks {
+            write!(output_file, "{}\t", ranks[i])?;
+        }
         output_file.write_all(onegram.as_bytes())?;
+        if let Some(values) = &values {
+            write!(output_file, "\t{}", values[i])?;
+        }
         output_file.write_all(b"\n")?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frequencies::{FrequenciesHeader, FrequenciesWriter};
+
+    /// Write a frequencies file of `unigrams`, without bigrams.
+    fn write_frequencies(path: &Path, unigrams: &[(&str, u64)]) {
+        let header = FrequenciesHeader {
+            total_unigrams: unigrams.iter().map(|(_word, count)| count).sum(),
+            total_articles: None,
+            unigrams: unigrams.len() as u64,
+            bigrams: 0,
+        };
+        let mut writer = FrequenciesWriter::new(File::create(path).unwrap(), &header).unwrap();
+        writer.begin_unigrams().unwrap();
+        for (word, count) in unigrams {
+            writer.write_unigram(word, *count).unwrap();
+        }
+        writer.begin_bigrams().unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn word_lists_may_start_with_a_rank() {
+        let dir = tempfile::tempdir().unwrap();
+        let cases = [
+            ("the\nof\n", vec!["the", "of"]),
+            ("the\t1234.5\nof\t678.9\n", vec!["the", "of"]),
+            ("1\tthe\n2\tof\n", vec!["the", "of"]),
+            ("00001\tthe\t1234.5\n00002\tof\t678.9\n", vec!["the", "of"]),
+            ("1990\t12.3\nthe\t1234.5\n", vec!["1990", "the"]),
+            ("1990\t12.3\n2000\t4.5\n", vec!["1990", "2000"]),
+            ("0\tthe\n1\tof\n", vec!["the", "of"]),
+            ("1990\n2000\n", vec!["1990", "2000"]),
+        ];
+        let input_file = dir.path().join("top.txt");
+        for (contents, expected) in &cases {
+            fs::write(&input_file, contents).unwrap();
+            assert_eq!(
+                load_word_list(&input_file).unwrap(),
+                *expected,
+                "{:?}",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn stable_against_ranks_are_line_numbers() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_file = dir.path().join("frequencies.txt");
+        write_frequencies(
+            &input_file,
+            &[("the", 50), ("of", 40), ("and", 30), ("to", 20)],
+        );
+        let output_file = dir.path().join("top.txt");
+        let previous_words: Vec<String> = ["of", "the", "zzz"]
+            .iter()
+            .map(|word| word.to_string())
+            .collect();
+        handle_top_k_words(
+            &input_file,
+            &[(output_file.clone(), 1)],
+            3,
+            false,
+            false,
+            "",
+            OutputColumn::None,
+            Some(RankFormat { base: 1, width: 0 }),
+            false,
+            None,
+            Some(&previous_words),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&output_file).unwrap(),
+            "1\tof\n2\tthe\n3\tand\n"
+        );
+    }
+}
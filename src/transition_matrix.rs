@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::frequencies::load_bigrams;
+use crate::topkwords::load_sorted_onegrams;
+
+/// How `transition-matrix` writes the matrix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatrixFormat {
+    /// `w1\tw2\tprob` lines for the non-zero entries only, rows in order of frequency.
+    Sparse,
+
+    /// A CSV file with a header row of words and one row per word, every entry written.
+    Dense,
+}
+
+impl FromStr for MatrixFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "sparse" => Ok(MatrixFormat::Sparse),
+            "dense" => Ok(MatrixFormat::Dense),
+            _ => Err(format!("Unknown matrix format {}", input)),
+        }
+    }
+}
+
+/// Write the transition matrix P(w2 | w1) between the `top_k` most frequent words, estimated as
+/// the bigram count of (w1, w2) divided by the unigram count of w1. Successors outside of the
+/// top K are left out, so rows sum to at most 1, the remainder being the probability of moving
+/// to any other word.
+pub fn handle_transition_matrix(
+    input_file: &Path,
+    output_file: &Path,
+    top_k: usize,
+    format: MatrixFormat,
+) -> Result<(), Box<dyn Error>> {
+    let top_words: Vec<(String, u64)> = load_sorted_onegrams(input_file)?
+        .into_iter()
+        .take(top_k)
+        .collect();
+    let word_indices: HashMap<&str, usize> = top_words
+        .iter()
+        .enumerate()
+        .map(|(i, (word, _count))| (word.as_str(), i))
+        .collect();
+    let mut rows: Vec<Vec<(usize, f64)>> = vec![Vec::new(); top_words.len()];
+    for ((word1, word2), count) in load_bigrams(input_file, true)? {
+        if let (Some(&i), Some(&j)) = (
+            word_indices.get(word1.as_str()),
+            word_indices.get(word2.as_str()),
+        ) {
+            rows[i].push((j, count as f64 / top_words[i].1 as f64));
+        }
+    }
+
+    let output_file = File::create(output_file).map_err(|err| {
+        format!(
+            "Could not create output file {:?} due to {:?}",
+            output_file, err
+        )
+    })?;
+    let mut output_file = BufWriter::new(output_file);
+    match format {
+        MatrixFormat::Sparse => {
+            for (i, row) in rows.iter_mut().enumerate() {
+                row.sort_by_key(|(j, _probability)| *j);
+                for (j, probability) in row.iter() {
+                    writeln!(
+                        output_file,
+                        "{}\t{}\t{:.6e}",
+                        top_words[i].0, top_words[*j].0, probability
+                    )?;
+                }
+            }
+        }
+        MatrixFormat::Dense => {
+            for (word, _count) in top_words.iter() {
+                write!(output_file, ",{}", csv_field(word))?;
+            }
+            writeln!(output_file)?;
+            let mut dense_row = vec![0.0; top_words.len()];
+            for (i, row) in rows.iter().enumerate() {
+                dense_row.fill(0.0);
+                for (j, probability) in row.iter() {
+                    dense_row[*j] = *probability;
+                }
+                write!(output_file, "{}", csv_field(&top_words[i].0))?;
+                for probability in dense_row.iter() {
+                    write!(output_file, ",{:.6e}", probability)?;
+                }
+                writeln!(output_file)?;
+            }
+        }
+    }
+    output_file.flush()?;
+    Ok(())
+}
+
+/// Quote a CSV field if it contains a comma or a double quote, doubling any double quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,84 +1,760 @@
-use flate2::read::GzDecoder;
-use std::cell::RefCell;
-use std::collections::HashSet;
+use encoding_rs::Encoding;
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::str::FromStr;
+use unicode_normalization::char::is_combining_mark;
 use unicode_normalization::UnicodeNormalization;
 
 /// If a word is not in the dictionry change it to this. This will never appear in the corpus
 /// because we trim puncutation from the beginning and ends of words.
 pub const OUT_OF_VOCABULARY_WORD: &str = "<unk>";
 
+/// Iterates over the lines of a file, gzip-decompressing it if it ends in `.gz`, reusing one
+/// buffer for every line. A read error, e.g. a corrupt or truncated gzip stream, is returned
+/// once and then iteration stops, so that it can't be mistaken for the end of the file. Lines
+/// must be UTF-8 unless an encoding is set with `with_encoding`.
 pub struct LineIterator {
     reader: Box<dyn BufRead>,
     buf: Rc<RefCell<String>>,
+    failed: bool,
+    encoding: Option<&'static Encoding>,
+    bytes: Vec<u8>,
 }
 
 impl LineIterator {
     pub fn new(input_file: &Path) -> Result<LineIterator, Box<dyn Error>> {
-        let file = File::open(input_file).unwrap();
-        match input_file.extension().and_then(OsStr::to_str) {
-            Some("gz") => {
-                let file = GzDecoder::new(file);
-                let file = BufReader::new(file);
-                Ok(LineIterator {
-                    reader: Box::new(file),
-                    buf: Rc::new(RefCell::new(String::new())),
-                })
-            }
-            _ => {
-                let file = BufReader::new(file);
-                Ok(LineIterator {
-                    reader: Box::new(file),
-                    buf: Rc::new(RefCell::new(String::new())),
-                })
-            }
+        Ok(LineIterator::from_reader(open_lines_file(input_file)?))
+    }
+
+    /// Iterate over the lines of an already opened reader.
+    pub fn from_reader(reader: Box<dyn BufRead>) -> LineIterator {
+        LineIterator {
+            reader,
+            buf: Rc::new(RefCell::new(String::new())),
+            failed: false,
+            encoding: None,
+            bytes: Vec::new(),
         }
     }
+
+    /// Decode lines from `encoding`, which must be ASCII-compatible so that lines can be split on
+    /// '\n' bytes, rather than requiring UTF-8. Malformed sequences become U+FFFD. `None` keeps
+    /// requiring UTF-8.
+    pub fn with_encoding(mut self, encoding: Option<&'static Encoding>) -> LineIterator {
+        self.encoding = encoding;
+        self
+    }
+
+    fn read_line(&mut self) -> io::Result<usize> {
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => return self.reader.read_line(&mut self.buf.borrow_mut()),
+        };
+        self.bytes.clear();
+        let length = self.reader.read_until(b'\n', &mut self.bytes)?;
+        let (decoded, _had_errors) = encoding.decode_without_bom_handling(&self.bytes);
+        self.buf.borrow_mut().push_str(&decoded);
+        Ok(length)
+    }
 }
 
 impl Iterator for LineIterator {
-    type Item = Rc<RefCell<String>>;
+    type Item = io::Result<Rc<RefCell<String>>>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
         self.buf.borrow_mut().clear();
-        match self.reader.read_line(&mut self.buf.borrow_mut()) {
+        match self.read_line() {
             Ok(0) => None,
-            Ok(_) => Some(Rc::clone(&self.buf)),
-            Err(_) => None,
+            Ok(_) => Some(Ok(Rc::clone(&self.buf))),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
         }
     }
 }
 
-const EN_DICT: &[u8] = include_bytes!("dictionaries/en.txt");
-const PL_DICT: &[u8] = include_bytes!("dictionaries/pl.txt");
+/// Open a file to read lines from, gzip-decompressing it if it ends in `.gz`.
+fn open_lines_file(input_file: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(input_file)?;
+    match input_file.extension().and_then(OsStr::to_str) {
+        // Split pieces may consist of several gzip members, e.g. after a resumed split.
+        Some("gz") => Ok(Box::new(BufReader::new(MultiGzDecoder::new(file)))),
+        _ => Ok(Box::new(BufReader::new(file))),
+    }
+}
 
-pub fn get_dictionary(language_code: &str) -> Result<HashSet<String>, Box<dyn Error>> {
-    let dict_bytes = match language_code {
-        "en" => Ok(EN_DICT),
-        "pl" => Ok(PL_DICT),
-        _ => {
-            let err: Box<dyn Error> =
-                format!("No dictionary available for language {}", language_code).into();
-            Err(err)
+/// Something to count lines of independently: a whole file, or one piece, a byte range of
+/// concatenated gzip members, of a `split --single-file` archive.
+#[derive(Clone, Debug)]
+pub struct InputPiece {
+    path: PathBuf,
+    range: Option<PieceRange>,
+}
+
+#[derive(Clone, Debug)]
+struct PieceRange {
+    name: String,
+    offset: u64,
+    length: u64,
+}
+
+impl InputPiece {
+    pub fn whole_file(path: PathBuf) -> InputPiece {
+        InputPiece { path, range: None }
+    }
+
+    pub fn archive_piece(path: PathBuf, name: String, offset: u64, length: u64) -> InputPiece {
+        InputPiece {
+            path,
+            range: Some(PieceRange {
+                name,
+                offset,
+                length,
+            }),
         }
-    };
-    let dict = io::Cursor::new(dict_bytes?);
-    let dict = BufReader::new(dict);
-    let dict = dict
-        .lines()
-        .map(|result| result.unwrap())
-        .map(|line| line.nfkc().collect::<String>())
-        .filter(|line| !line.starts_with('#'))
-        .map(|line| {
-            String::from(line.trim_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace()))
+    }
+
+    /// Size of the piece on disk, i.e. compressed, a proxy for how long it takes to count.
+    pub fn compressed_size(&self) -> io::Result<u64> {
+        match &self.range {
+            Some(range) => Ok(range.length),
+            None => Ok(fs::metadata(&self.path)?.len()),
+        }
+    }
+
+    /// The file the piece is in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Name of the piece, unique among the pieces of one split.
+    pub fn name(&self) -> String {
+        match &self.range {
+            Some(range) => range.name.clone(),
+            None => self
+                .path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    pub fn lines(&self) -> Result<LineIterator, Box<dyn Error>> {
+        Ok(LineIterator::from_reader(self.open()?))
+    }
+
+    /// The decompressed contents of the piece, for reading lines as bytes rather than strings.
+    pub fn open(&self) -> io::Result<Box<dyn BufRead>> {
+        let range = match &self.range {
+            Some(range) => range,
+            None => return open_lines_file(&self.path),
+        };
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(range.offset))?;
+        let piece = MultiGzDecoder::new(file.take(range.length));
+        Ok(Box::new(BufReader::new(piece)))
+    }
+}
+
+impl fmt::Display for InputPiece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.range {
+            Some(range) => write!(f, "{:?} piece {}", self.path, range.name),
+            None => write!(f, "{:?}", self.path),
+        }
+    }
+}
+
+/// Errors if `output_file` exists, unless `force` is set, so that re-running a sub-command doesn't
+/// silently replace output that took hours to produce.
+pub fn check_not_overwriting(output_file: &Path, force: bool) -> Result<(), Box<dyn Error>> {
+    if !force && output_file.exists() {
+        let err: Box<dyn Error> = format!(
+            "Output file {:?} already exists, pass --force to overwrite it",
+            output_file
+        )
+        .into();
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// The file name to embed in the gzip header of a file written to `gzip_path`: its file name
+/// without directories or the `.gz` extension, i.e. what `gunzip -N` would restore it as.
+pub fn gzip_member_filename(gzip_path: &Path) -> String {
+    let file_name = gzip_path.file_name().unwrap_or_default().to_string_lossy();
+    file_name
+        .strip_suffix(".gz")
+        .unwrap_or(&file_name)
+        .to_string()
+}
+
+/// Peak resident set size of this process, from `VmHWM` in `/proc/self/status`. `None` where that
+/// isn't available, i.e. on anything but Linux.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// How an input file is compressed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputCompression {
+    /// Decide from the file extension, falling back to checking for the gzip and bzip2 magic
+    /// bytes.
+    Auto,
+    Gzip,
+
+    /// Only supported when built with the `bzip2` feature, otherwise opening the file errors.
+    Bzip2,
+    None,
+}
+
+impl FromStr for InputCompression {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "auto" => Ok(InputCompression::Auto),
+            "gzip" => Ok(InputCompression::Gzip),
+            "bzip2" => Ok(InputCompression::Bzip2),
+            "none" => Ok(InputCompression::None),
+            _ => Err(format!("Unknown input compression {}", input)),
+        }
+    }
+}
+
+/// A writing system that `create-frequencies --script` restricts tokens to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Han,
+}
+
+impl FromStr for Script {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "latin" => Ok(Script::Latin),
+            "greek" => Ok(Script::Greek),
+            "cyrillic" => Ok(Script::Cyrillic),
+            "hebrew" => Ok(Script::Hebrew),
+            "arabic" => Ok(Script::Arabic),
+            "han" => Ok(Script::Han),
+            _ => Err(format!("Unknown script {}", input)),
+        }
+    }
+}
+
+impl Script {
+    /// The Unicode blocks of the script's letters, as inclusive ranges of code points.
+    fn blocks(self) -> &'static [(char, char)] {
+        match self {
+            Script::Latin => &[
+                ('A', 'Z'),
+                ('a', 'z'),
+                ('\u{aa}', '\u{aa}'),
+                ('\u{ba}', '\u{ba}'),
+                ('\u{c0}', '\u{d6}'),
+                ('\u{d8}', '\u{f6}'),
+                ('\u{f8}', '\u{2af}'),
+                ('\u{1d00}', '\u{1d7f}'),
+                ('\u{1e00}', '\u{1eff}'),
+                ('\u{2c60}', '\u{2c7f}'),
+                ('\u{a720}', '\u{a7ff}'),
+                ('\u{ab30}', '\u{ab6f}'),
+                ('\u{fb00}', '\u{fb06}'),
+                ('\u{ff21}', '\u{ff3a}'),
+                ('\u{ff41}', '\u{ff5a}'),
+            ],
+            Script::Greek => &[('\u{370}', '\u{3ff}'), ('\u{1f00}', '\u{1fff}')],
+            Script::Cyrillic => &[
+                ('\u{400}', '\u{52f}'),
+                ('\u{1c80}', '\u{1c8f}'),
+                ('\u{2de0}', '\u{2dff}'),
+                ('\u{a640}', '\u{a69f}'),
+            ],
+            Script::Hebrew => &[('\u{590}', '\u{5ff}'), ('\u{fb1d}', '\u{fb4f}')],
+            Script::Arabic => &[
+                ('\u{600}', '\u{6ff}'),
+                ('\u{750}', '\u{77f}'),
+                ('\u{8a0}', '\u{8ff}'),
+                ('\u{fb50}', '\u{fdff}'),
+                ('\u{fe70}', '\u{feff}'),
+            ],
+            Script::Han => &[
+                ('\u{2e80}', '\u{2fdf}'),
+                ('\u{3005}', '\u{3007}'),
+                ('\u{3021}', '\u{3029}'),
+                ('\u{3400}', '\u{4dbf}'),
+                ('\u{4e00}', '\u{9fff}'),
+                ('\u{f900}', '\u{faff}'),
+                ('\u{20000}', '\u{3ffff}'),
+            ],
+        }
+    }
+
+    /// Whether every letter of `token` is in this script. Characters common to every script,
+    /// i.e. digits, punctuation, symbols and combining marks, are allowed too, so "3D", "naïve"
+    /// in NFD and "e-mail" are Latin, but a letter of another script, as in "Москва" or a
+    /// Latin word with a Cyrillic lookalike letter, isn't.
+    pub fn is_written_in(self, token: &str) -> bool {
+        token.chars().all(|c| {
+            !c.is_alphabetic()
+                || is_combining_mark(c)
+                || self
+                    .blocks()
+                    .iter()
+                    .any(|(first, last)| (*first..=*last).contains(&c))
         })
-        .filter(|line| !line.is_empty())
-        .collect();
-    Ok(dict)
+    }
+}
+
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+const BZIP2_MAGIC_BYTES: [u8; 3] = *b"BZh";
+
+/// Shared count of bytes read from a file, see `open_input`.
+pub type BytesRead = Rc<Cell<u64>>;
+
+/// Wraps a reader, counting the bytes read through it into a shared counter. Wrapping the file
+/// underneath a decoder gives the position in the compressed file, for progress reporting.
+struct CountingReader<R: Read> {
+    inner: R,
+    bytes_read: BytesRead,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes_read.set(self.bytes_read.get() + read as u64);
+        Ok(read)
+    }
+}
+
+/// Open an input file for reading lines, decompressing it if needed. Also returns a counter of the
+/// bytes consumed from the file itself, before decompression, which can be compared against the
+/// file size to estimate progress.
+pub fn open_input(
+    input_path: &Path,
+    compression: InputCompression,
+) -> Result<(Box<dyn BufRead>, BytesRead), Box<dyn Error>> {
+    let compression = match compression {
+        InputCompression::Auto => detect_compression(input_path)?,
+        compression => compression,
+    };
+    let bytes_read = Rc::new(Cell::new(0));
+    let file = CountingReader {
+        inner: File::open(input_path)?,
+        bytes_read: Rc::clone(&bytes_read),
+    };
+    let reader: Box<dyn BufRead> = match compression {
+        InputCompression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        InputCompression::Bzip2 => bzip2_reader(file, input_path)?,
+        _ => Box::new(BufReader::new(file)),
+    };
+    Ok((reader, bytes_read))
+}
+
+/// Multi-stream, as Wikimedia's `multistream` dumps and pbzip2's output are.
+#[cfg(feature = "bzip2")]
+fn bzip2_reader<R: Read + 'static>(
+    file: R,
+    _input_path: &Path,
+) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    Ok(Box::new(BufReader::new(bzip2::read::MultiBzDecoder::new(
+        file,
+    ))))
+}
+
+#[cfg(not(feature = "bzip2"))]
+fn bzip2_reader<R: Read + 'static>(
+    _file: R,
+    input_path: &Path,
+) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    let err: Box<dyn Error> = format!(
+        "{:?} is bzip2-compressed, which needs a build with 'cargo build --features bzip2'",
+        input_path
+    )
+    .into();
+    Err(err)
+}
+
+fn detect_compression(input_path: &Path) -> Result<InputCompression, Box<dyn Error>> {
+    match input_path.extension().and_then(OsStr::to_str) {
+        Some("gz") => Ok(InputCompression::Gzip),
+        Some("bz2") => Ok(InputCompression::Bzip2),
+        Some("jsonl") | Some("ndjson") | Some("json") => Ok(InputCompression::None),
+        _ => {
+            let mut magic_bytes = [0; 3];
+            let mut file = File::open(input_path)?;
+            match file.read_exact(&mut magic_bytes) {
+                Ok(()) if magic_bytes[..2] == GZIP_MAGIC_BYTES => Ok(InputCompression::Gzip),
+                Ok(()) if magic_bytes == BZIP2_MAGIC_BYTES => Ok(InputCompression::Bzip2),
+                _ => Ok(InputCompression::None),
+            }
+        }
+    }
+}
+
+// Gzip-compressed copies of src/dictionaries, written by build.rs.
+const EN_DICT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/en.txt.gz"));
+const PL_DICT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/pl.txt.gz"));
+
+/// Counts gathered while loading a dictionary, useful for diagnosing high OOV rates.
+#[derive(Debug, Default)]
+pub struct DictionaryStats {
+    /// Number of lines in the embedded dictionary file.
+    pub raw_lines: u64,
+
+    /// Number of lines left after dropping comments and lines that are empty once trimmed.
+    pub filtered_lines: u64,
+
+    /// Number of filtered lines that NFKC normalization changed.
+    pub nfkc_changed_lines: u64,
+
+    /// Number of distinct entries in the loaded dictionary.
+    pub unique_entries: u64,
+
+    /// Number of filtered lines that collapsed into an existing entry, including those merged by
+    /// case folding.
+    pub duplicates_collapsed: u64,
+
+    /// Number of entries that only collapsed into an existing entry because of case folding.
+    pub case_folded_entries: u64,
+}
+
+/// ASCII punctuation that is part of words in a language, and so must not be trimmed from the
+/// ends of tokens or dictionary entries. Neither of the current languages needs any, but e.g. a
+/// transliterated language that writes a soft sign as an apostrophe would protect '\''.
+const PROTECTED_PUNCTUATION: &[(&str, &[char])] = &[("en", &[]), ("pl", &[])];
+
+/// The protected punctuation of any of a comma-separated list of languages.
+fn protected_punctuation(language_codes: &str) -> Vec<char> {
+    let mut result = Vec::new();
+    for language_code in language_codes.split(',') {
+        let protected = PROTECTED_PUNCTUATION
+            .iter()
+            .find(|(code, _)| *code == language_code)
+            .map_or(&[][..], |(_, protected)| *protected);
+        for c in protected {
+            if !result.contains(c) {
+                result.push(*c);
+            }
+        }
+    }
+    result
+}
+
+/// Which characters are trimmed from both ends of tokens and dictionary entries. Whitespace is
+/// always trimmed.
+#[derive(Clone, Debug, Default)]
+pub struct TrimChars {
+    /// Trim only these, instead of all ASCII punctuation.
+    pub only: Option<Vec<char>>,
+
+    /// Never trim these, e.g. a language's protected punctuation.
+    pub keep: Vec<char>,
+}
+
+impl TrimChars {
+    /// The default for a comma-separated list of languages: ASCII punctuation other than the
+    /// languages' protected punctuation.
+    pub fn for_languages(language_codes: &str) -> TrimChars {
+        TrimChars {
+            only: None,
+            keep: protected_punctuation(language_codes),
+        }
+    }
+
+    fn is_trimmed(&self, c: char) -> bool {
+        if c.is_whitespace() {
+            return true;
+        }
+        let is_candidate = match &self.only {
+            Some(only) => only.contains(&c),
+            None => c.is_ascii_punctuation(),
+        };
+        is_candidate && !self.keep.contains(&c)
+    }
+}
+
+/// Trim the characters selected by `trim_chars` from both ends of a token. Used for both corpus
+/// tokens and dictionary entries so that they stay comparable.
+pub fn trim_token<'a>(token: &'a str, trim_chars: &TrimChars) -> &'a str {
+    token.trim_matches(|c: char| trim_chars.is_trimmed(c))
+}
+
+/// The natural logarithm of the probability `count / total`, as the difference of the logarithms
+/// of the counts rather than the logarithm of their quotient. Counts above 2^53, the largest
+/// integer `f64` represents exactly, are rounded when cast, but by a relative error of at most
+/// 2^-53, which `ln` turns into an absolute error of about 1e-16 however large the counts, while
+/// a quotient of two tiny probabilities, as in PMI, can underflow. `-inf` for a count of 0.
+pub fn ln_probability(count: u64, total: u64) -> f64 {
+    (count as f64).ln() - (total as f64).ln()
+}
+
+/// Decompose `text` to NFD and drop its combining marks, folding accented letters to their base
+/// letter, e.g. "café" to "cafe". Letters without a decomposition, like the Polish "ł", are kept.
+pub fn strip_combining_marks(text: &str) -> String {
+    text.nfd().filter(|c| !is_combining_mark(*c)).collect()
+}
+
+/// The letters of a language's alphabet that are letters of their own rather than accented forms
+/// of another, each with the letter it follows, in alphabet order, e.g. Polish "ł" after "l".
+/// `None` for languages without a collation, see `collation_key`.
+fn own_letters(language_code: &str) -> Option<&'static [(char, char)]> {
+    match language_code {
+        "en" => Some(&[]),
+        "pl" => Some(&[
+            ('ą', 'a'),
+            ('ć', 'c'),
+            ('ę', 'e'),
+            ('ł', 'l'),
+            ('ń', 'n'),
+            ('ó', 'o'),
+            ('ś', 's'),
+            ('ź', 'z'),
+            ('ż', 'z'),
+        ]),
+        _ => None,
+    }
+}
+
+/// Collation key of one letter in the alphabet order of a language.
+type CollationLetter = (char, usize);
+
+/// A key sorting words alphabetically in the alphabet order of `language_code`, ignoring case:
+/// letters of its own, e.g. Polish "ł", sort right after the letter they follow, and other
+/// accented letters with their base letter, accents and then case only breaking ties, so that
+/// "cafe" < "Café" < "café" < "cafes". Without a collation for the language, words are ordered
+/// by the code points of their lowercased form, then case.
+pub fn collation_key(word: &str, language_code: &str) -> (Vec<CollationLetter>, String, String) {
+    let lowercase = word.to_lowercase();
+    let mut letters = Vec::with_capacity(lowercase.len());
+    match own_letters(language_code) {
+        Some(own_letters) => {
+            for c in lowercase.chars() {
+                match own_letters
+                    .iter()
+                    .position(|(letter, _follows)| *letter == c)
+                {
+                    Some(i) => letters.push((own_letters[i].1, i + 1)),
+                    None => letters.extend(
+                        c.to_string()
+                            .nfd()
+                            .filter(|c| !is_combining_mark(*c))
+                            .map(|c| (c, 0)),
+                    ),
+                }
+            }
+        }
+        None => letters.extend(lowercase.chars().map(|c| (c, 0))),
+    }
+    (letters, lowercase, word.to_string())
+}
+
+/// Load the embedded dictionaries for a comma-separated list of languages, e.g. "en" or "en,pl",
+/// into one set. Words shared between the languages collapse into a single entry. With
+/// `fold_case` every entry is lowercased, so e.g. "Polska" and "polska" become a single entry,
+/// and callers must lowercase tokens before looking them up. Entries are trimmed with
+/// `trim_chars`, which must be what tokens are trimmed with. With `strip_accents` entries are
+/// stripped of accents with `strip_combining_marks`, as tokens must be too.
+pub fn get_dictionary_with_stats(
+    language_codes: &str,
+    fold_case: bool,
+    trim_chars: &TrimChars,
+    strip_accents: bool,
+) -> Result<(HashSet<String>, DictionaryStats), Box<dyn Error>> {
+    let mut dicts = Vec::new();
+    for language_code in language_codes.split(',') {
+        let dict_bytes = match language_code {
+            "en" => Ok(EN_DICT),
+            "pl" => Ok(PL_DICT),
+            _ => {
+                let err: Box<dyn Error> =
+                    format!("No dictionary available for language {}", language_code).into();
+                Err(err)
+            }
+        };
+        dicts.push(dict_bytes?);
+    }
+    let mut stats = DictionaryStats::default();
+    let mut result = HashSet::new();
+    let mut exact_case_entries = HashSet::new();
+    let lines = dicts
+        .into_iter()
+        .flat_map(|dict_bytes| BufReader::new(GzDecoder::new(dict_bytes)).lines());
+    for line in lines {
+        let line = line?;
+        stats.raw_lines += 1;
+        let normalized = line.nfkc().collect::<String>();
+        if normalized.starts_with('#') {
+            continue;
+        }
+        let (entry, _weight) = split_dictionary_weight(&normalized)?;
+        let word = trim_token(entry, trim_chars);
+        let stripped;
+        let word = if strip_accents {
+            stripped = strip_combining_marks(word);
+            stripped.as_str()
+        } else {
+            word
+        };
+        if word.is_empty() {
+            continue;
+        }
+        stats.filtered_lines += 1;
+        if normalized != line {
+            stats.nfkc_changed_lines += 1;
+        }
+        let entry = if fold_case {
+            let is_new_exact_case = exact_case_entries.insert(String::from(word));
+            let folded = word.to_lowercase();
+            if is_new_exact_case && result.contains(&folded) {
+                stats.case_folded_entries += 1;
+            }
+            folded
+        } else {
+            String::from(word)
+        };
+        if !result.insert(entry) {
+            stats.duplicates_collapsed += 1;
+        }
+    }
+    stats.unique_entries = result.len() as u64;
+    Ok((result, stats))
+}
+
+/// Split a dictionary line into its entry and the entry's prior weight, e.g. its frequency in a
+/// reference corpus, from an optional second tab-separated column. Entries without one have a
+/// weight of 1.0, so that a dictionary without weights is a uniform prior.
+pub fn split_dictionary_weight(line: &str) -> Result<(&str, f64), String> {
+    match line.split_once('\t') {
+        Some((entry, weight)) => match weight.trim().parse::<f64>() {
+            Ok(weight) if weight.is_finite() && weight >= 0.0 => Ok((entry, weight)),
+            _ => Err(format!(
+                "Dictionary weight {:?} is not a non-negative number",
+                weight
+            )),
+        },
+        None => Ok((line, 1.0)),
+    }
+}
+
+/// Load the prior weights of a dictionary file, one `word` or `word\tweight` line per entry, see
+/// `split_dictionary_weight`. Lines are NFKC-normalized and comments and empty lines skipped as for
+/// the embedded dictionaries, but words are only trimmed of whitespace, since they are matched
+/// against words already counted. A word listed more than once keeps its last weight.
+pub fn load_dictionary_priors(path: &Path) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+    let file = File::open(path)
+        .map_err(|err| format!("Could not open dictionary file {:?} due to {:?}", path, err))?;
+    let mut priors = HashMap::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let normalized = line?.nfkc().collect::<String>();
+        if normalized.starts_with('#') {
+            continue;
+        }
+        let (word, weight) = split_dictionary_weight(&normalized)
+            .map_err(|err| format!("{} on line {} of {:?}", err, i + 1, path))?;
+        let word = word.trim();
+        if !word.is_empty() {
+            priors.insert(word.to_string(), weight);
+        }
+    }
+    Ok(priors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn truncated_gzip_returns_one_error_and_then_stops() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut compressed = GzEncoder::new(Vec::new(), Compression::default());
+        for i in 0..10_000 {
+            writeln!(compressed, "line {} of a file that gets truncated", i).unwrap();
+        }
+        let compressed = compressed.finish().unwrap();
+        let input_file = dir.path().join("truncated.gz");
+        fs::write(&input_file, &compressed[..compressed.len() / 2]).unwrap();
+
+        let mut lines = LineIterator::new(&input_file).unwrap();
+        let mut read_lines = 0;
+        loop {
+            match lines.next() {
+                Some(Ok(_line)) => read_lines += 1,
+                Some(Err(_err)) => break,
+                None => panic!("truncated input ended without an error"),
+            }
+        }
+        assert!(read_lines < 10_000);
+        assert!(lines.next().is_none());
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn ln_probability_stays_accurate_beyond_exact_f64_integers() {
+        use std::f64::consts::LN_2;
+        // Each case's exact value, from writing counts as 2^k * (1 + e).
+        let cases = [
+            (1, (1 << 53) + 1, -(53.0 * LN_2 + 2f64.powi(-53).ln_1p())),
+            (
+                (1 << 53) + 1,
+                (1 << 60) + 3,
+                (53.0 * LN_2 + 2f64.powi(-53).ln_1p())
+                    - (60.0 * LN_2 + (3.0 * 2f64.powi(-60)).ln_1p()),
+            ),
+            (1, u64::MAX, -(64.0 * LN_2 + (-(2f64.powi(-64))).ln_1p())),
+            (3, 1 << 62, 3f64.ln() - 62.0 * LN_2),
+        ];
+        for (count, total, exact) in &cases {
+            let ln_probability = ln_probability(*count, *total);
+            assert!(ln_probability.is_finite(), "{} / {}", count, total);
+            assert!(
+                (ln_probability - exact).abs() < 1e-12,
+                "{} / {}: {} != {}",
+                count,
+                total,
+                ln_probability,
+                exact
+            );
+        }
+    }
+
+    #[test]
+    fn gzip_member_filename_drops_directories_and_the_gz_extension() {
+        assert_eq!(
+            gzip_member_filename(Path::new("/tmp/out/frequencies.txt.gz")),
+            "frequencies.txt"
+        );
+        assert_eq!(
+            gzip_member_filename(Path::new("frequencies")),
+            "frequencies"
+        );
+    }
 }
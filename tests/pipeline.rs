@@ -0,0 +1,69 @@
+//! Runs `make-fixtures`, `split`, `create-frequencies` and `top-k-words` end to end, so that a
+//! change to how the sub-commands hand files to each other, e.g. `split`'s piece file names, which
+//! `create-frequencies` finds by the `split` in their names, breaks a test.
+
+use std::fs;
+
+use word_frequencies::create_frequencies::{handle_create_frequencies, CreateFrequenciesOptions};
+use word_frequencies::fixtures::handle_make_fixtures;
+use word_frequencies::split::{handle_split, PieceAssignment, DEFAULT_TEXT_POINTER};
+use word_frequencies::topkwords::{handle_top_k_words, OutputColumn};
+use word_frequencies::util::InputCompression;
+
+#[test]
+fn fixture_words_come_out_of_the_pipeline_in_vocabulary_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let vocabulary: Vec<String> = ["the", "of", "and", "to", "in", "was", "is", "for"]
+        .iter()
+        .map(|word| word.to_string())
+        .collect();
+    handle_make_fixtures(dir.path(), 1000, 50, &vocabulary, 42).unwrap();
+
+    let split_dir = dir.path().join("split");
+    handle_split(
+        &dir.path().join("fixture-cirrussearch.json.gz"),
+        &split_dir,
+        12,
+        PieceAssignment::Random,
+        false,
+        InputCompression::Auto,
+        1,
+        None,
+        false,
+        None,
+        DEFAULT_TEXT_POINTER,
+        false,
+    )
+    .unwrap();
+
+    let frequencies_file = dir.path().join("frequencies.txt");
+    handle_create_frequencies(
+        &split_dir,
+        frequencies_file.to_str().unwrap(),
+        "en",
+        &CreateFrequenciesOptions::for_language("en"),
+    )
+    .unwrap();
+
+    let top_file = dir.path().join("top.txt");
+    handle_top_k_words(
+        &dir.path().join("frequencies.txt.gz"),
+        &[(top_file.clone(), 2)],
+        5,
+        false,
+        false,
+        "",
+        OutputColumn::None,
+        None,
+        false,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let top = fs::read_to_string(&top_file).unwrap();
+    assert_eq!(
+        top.lines().collect::<Vec<_>>(),
+        vec!["the", "of", "and", "to", "in"]
+    );
+}